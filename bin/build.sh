@@ -1,7 +1,6 @@
 export RUST_BACKTRACE=1
 
 # Build the client Bundle
-pkill -f "http-server"
 set -e
 dir="$( cd "$( dirname "${BASH_SOURCE[0]}" )" && pwd )"
 project_dir=${dir}/..
@@ -12,6 +11,7 @@ cd crates/client
 cargo web build
 wasm-pack build --target web
 /usr/local/bin/rollup ./www/main.js --format iife --file ./pkg/bundle.js
+cp ./www/index.html ./www/style.css ./pkg/
 
 # Move it to root dir
 rm -r ../../pkg || true
@@ -19,9 +19,6 @@ mv pkg ../../pkg
 
 cd ../../
 
-# Serve the files
-nohup http-server -p 8085 --cors -c-1 &
-
-# Run the Rust App
+# Run the Rust App, which serves the client from pkg/
 echo "Running from $(pwd)"
 cargo run
@@ -0,0 +1,81 @@
+use zn_core::messages::{
+    Edit, FindNextParams, FindParams, HighlightFindParams, ReplaceParams, SelectionModifier, ViewId,
+};
+
+pub enum FindMsg {
+    /// Shows the find bar, with the replace field if `replace` is set.
+    Open {
+        replace: bool,
+    },
+    Close,
+    Query(String),
+    Replacement(String),
+    ToggleCaseSensitive,
+    ToggleRegex,
+    ToggleWholeWords,
+    Next,
+    Previous,
+    All,
+    ReplaceNext,
+    ReplaceAll,
+    /// The query field gained or lost the keyboard focus.
+    Focused(bool),
+}
+
+/// The state of the find bar, which searches the focused view.
+#[derive(Default)]
+pub struct Find {
+    pub open: bool,
+    pub replacing: bool,
+    pub query: String,
+    pub replacement: String,
+    pub case_sensitive: bool,
+    pub regex: bool,
+    pub whole_words: bool,
+}
+
+impl Find {
+    pub fn find(&self, view_id: &ViewId) -> Edit {
+        Edit::Find {
+            params: FindParams {
+                chars: self.query.clone(),
+                case_sensitive: self.case_sensitive,
+                regex: self.regex,
+                whole_words: self.whole_words,
+            },
+            view_id: view_id.clone(),
+        }
+    }
+
+    /// Selects the next match, or the previous one if not `forward`.
+    pub fn find_next(&self, view_id: &ViewId, forward: bool) -> Edit {
+        let params = FindNextParams {
+            wrap_around: true,
+            allow_same: false,
+            modify_selection: SelectionModifier::Set,
+        };
+        let view_id = view_id.clone();
+        if forward {
+            Edit::FindNext { params, view_id }
+        } else {
+            Edit::FindPrevious { params, view_id }
+        }
+    }
+
+    pub fn highlight(&self, view_id: &ViewId, visible: bool) -> Edit {
+        Edit::HighlightFind {
+            params: HighlightFindParams { visible },
+            view_id: view_id.clone(),
+        }
+    }
+
+    pub fn replace(&self, view_id: &ViewId) -> Edit {
+        Edit::Replace {
+            params: ReplaceParams {
+                chars: self.replacement.clone(),
+                preserve_case: false,
+            },
+            view_id: view_id.clone(),
+        }
+    }
+}
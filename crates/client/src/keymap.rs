@@ -0,0 +1,741 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use stdweb::web::event::IKeyboardEvent;
+use zn_core::messages::{Edit, Keybinding, ViewId};
+
+/// A key as reported by the browser, along with the modifiers held.
+/// `key` is the DOM `KeyboardEvent.key` value, e.g. `"a"`, `"A"` or `"ArrowLeft"`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyPress {
+    pub key: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl KeyPress {
+    pub fn from_event<E: IKeyboardEvent>(e: &E) -> Self {
+        KeyPress {
+            key: e.key(),
+            ctrl: e.ctrl_key(),
+            alt: e.alt_key(),
+            shift: e.shift_key(),
+            meta: e.meta_key(),
+        }
+    }
+
+    /// Whether the key would type its own text, e.g. `a` but not `ctrl+a` or `Enter`.
+    fn is_text(&self) -> bool {
+        !self.ctrl && !self.meta && self.key.chars().count() == 1
+    }
+}
+
+/// A key with its modifiers, normalized so it can be used to look up a
+/// binding: letter keys are lowercase whether or not shift is held.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Chord {
+    pub key: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl Chord {
+    /// Parses chords written like `ctrl+shift+z`, `C-S-z` or `PageUp`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut chord = Chord {
+            key: String::new(),
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+        };
+
+        // emacs style modifiers, e.g. `C-x` or `C-M-f`
+        let mut rest = s;
+        while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+            match &rest[..1] {
+                "C" => chord.ctrl = true,
+                "M" => chord.alt = true,
+                "S" => chord.shift = true,
+                "s" => chord.meta = true,
+                _ => break,
+            }
+            rest = &rest[2..];
+        }
+
+        let mut parts = rest.split('+').peekable();
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                chord.key = normalize_key(part);
+                break;
+            }
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl = true,
+                "alt" | "option" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "meta" | "cmd" | "super" => chord.meta = true,
+                _ => return Err(format!("Unknown modifier `{}` in `{}`", part, s)),
+            }
+        }
+
+        if chord.key.is_empty() {
+            return Err(format!("Missing key in `{}`", s));
+        }
+        Ok(chord)
+    }
+}
+
+impl<'a> From<&'a KeyPress> for Chord {
+    fn from(press: &'a KeyPress) -> Self {
+        Chord {
+            key: normalize_key(&press.key),
+            ctrl: press.ctrl,
+            alt: press.alt,
+            shift: press.shift,
+            meta: press.meta,
+        }
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in &[
+            (self.ctrl, "ctrl"),
+            (self.alt, "alt"),
+            (self.shift, "shift"),
+            (self.meta, "meta"),
+        ] {
+            if *held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+/// Lowercases everything but the DOM's named keys, and accepts a few
+/// friendlier names for those.
+fn normalize_key(key: &str) -> String {
+    match key.to_lowercase().as_str() {
+        "left" | "arrowleft" => "ArrowLeft".to_string(),
+        "right" | "arrowright" => "ArrowRight".to_string(),
+        "up" | "arrowup" => "ArrowUp".to_string(),
+        "down" | "arrowdown" => "ArrowDown".to_string(),
+        "home" => "Home".to_string(),
+        "end" => "End".to_string(),
+        "pageup" => "PageUp".to_string(),
+        "pagedown" => "PageDown".to_string(),
+        "backspace" => "Backspace".to_string(),
+        "delete" | "del" => "Delete".to_string(),
+        "enter" | "return" => "Enter".to_string(),
+        "tab" => "Tab".to_string(),
+        "escape" | "esc" => "Escape".to_string(),
+        "space" => " ".to_string(),
+        lower => lower.to_string(),
+    }
+}
+
+/// The default bindings for editing the focused view.
+const EDITOR_BINDINGS: &[(&str, &str)] = &[
+    ("left", "move_left"),
+    ("right", "move_right"),
+    ("up", "move_up"),
+    ("down", "move_down"),
+    ("shift+left", "move_left_and_modify_selection"),
+    ("shift+right", "move_right_and_modify_selection"),
+    ("shift+up", "move_up_and_modify_selection"),
+    ("shift+down", "move_down_and_modify_selection"),
+    ("ctrl+left", "move_word_left"),
+    ("ctrl+right", "move_word_right"),
+    ("ctrl+shift+left", "move_word_left_and_modify_selection"),
+    ("ctrl+shift+right", "move_word_right_and_modify_selection"),
+    ("home", "move_to_left_end_of_line"),
+    ("end", "move_to_right_end_of_line"),
+    (
+        "shift+home",
+        "move_to_left_end_of_line_and_modify_selection",
+    ),
+    (
+        "shift+end",
+        "move_to_right_end_of_line_and_modify_selection",
+    ),
+    ("ctrl+home", "move_to_beginning_of_document"),
+    ("ctrl+end", "move_to_end_of_document"),
+    (
+        "ctrl+shift+home",
+        "move_to_beginning_of_document_and_modify_selection",
+    ),
+    (
+        "ctrl+shift+end",
+        "move_to_end_of_document_and_modify_selection",
+    ),
+    ("pageup", "scroll_page_up"),
+    ("pagedown", "scroll_page_down"),
+    ("shift+pageup", "page_up_and_modify_selection"),
+    ("shift+pagedown", "page_down_and_modify_selection"),
+    ("backspace", "delete_backward"),
+    ("shift+backspace", "delete_backward"),
+    ("delete", "delete_forward"),
+    ("ctrl+backspace", "delete_word_backward"),
+    ("ctrl+delete", "delete_word_forward"),
+    ("enter", "insert_newline"),
+    ("tab", "insert_tab"),
+    ("shift+tab", "outdent"),
+    ("ctrl+z", "undo"),
+    ("ctrl+shift+z", "redo"),
+    ("ctrl+y", "redo"),
+    ("ctrl+a", "select_all"),
+    ("ctrl+alt+up", "add_selection_above"),
+    ("ctrl+alt+down", "add_selection_below"),
+    ("escape", "collapse_selections"),
+];
+
+/// The default bindings while typing in the find bar.
+const FIND_BAR_BINDINGS: &[(&str, &str)] = &[
+    ("enter", "find_next"),
+    ("shift+enter", "find_previous"),
+    ("alt+enter", "find_all"),
+    ("escape", "close_find"),
+];
+
+/// The default bindings while the quick open dialog is up.
+const QUICK_OPEN_BINDINGS: &[(&str, &str)] = &[
+    ("up", "quick_open_previous"),
+    ("down", "quick_open_next"),
+    ("enter", "quick_open_accept"),
+    ("escape", "quick_open_close"),
+];
+
+/// The default bindings that work wherever the focus is.
+const GLOBAL_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+s", "save"),
+    ("ctrl+shift+s", "save_as"),
+    ("ctrl+w", "close_view"),
+    ("ctrl+f", "find"),
+    ("ctrl+h", "replace"),
+    ("ctrl+p", "quick_open"),
+    ("f3", "find_next"),
+    ("shift+f3", "find_previous"),
+    ("ctrl+pagedown", "next_tab"),
+    ("ctrl+pageup", "previous_tab"),
+    ("ctrl+shift+pagedown", "move_tab_right"),
+    ("ctrl+shift+pageup", "move_tab_left"),
+];
+
+/// Keys that only modify other keys, and never start or break a sequence.
+const MODIFIER_KEYS: &[&str] = &["Shift", "Control", "Alt", "Meta", "AltGraph", "CapsLock"];
+
+/// Where keyboard input is going, which bindings can be conditioned on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Focus {
+    Editor,
+    FindBar,
+    QuickOpen,
+}
+
+const FOCUSES: [Focus; 3] = [Focus::Editor, Focus::FindBar, Focus::QuickOpen];
+
+/// A binding's `when`, e.g. `find_bar_focused` or `!editor_focused`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Condition {
+    focus: Focus,
+    negated: bool,
+}
+
+impl Condition {
+    fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (negated, name) = match s.strip_prefix('!') {
+            Some(name) => (true, name.trim()),
+            None => (false, s),
+        };
+        let focus = match name {
+            "editor_focused" => Focus::Editor,
+            "find_bar_focused" => Focus::FindBar,
+            "quick_open_focused" => Focus::QuickOpen,
+            _ => return Err(format!("Unknown condition `{}`", s)),
+        };
+        Ok(Condition { focus, negated })
+    }
+
+    fn matches(self, focus: Focus) -> bool {
+        (self.focus == focus) != self.negated
+    }
+}
+
+struct Binding {
+    keys: Vec<Chord>,
+    command: String,
+    when: Option<Condition>,
+}
+
+impl Binding {
+    fn parse(keys: &str, command: &str, when: Option<&str>) -> Result<Self, String> {
+        let keys = keys
+            .split_whitespace()
+            .map(Chord::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            return Err(format!("No keys given for `{}`", command));
+        }
+        Ok(Binding {
+            keys,
+            command: command.to_string(),
+            when: when.map(Condition::parse).transpose()?,
+        })
+    }
+
+    fn applies(&self, focus: Focus) -> bool {
+        match self.when {
+            Some(when) => when.matches(focus),
+            None => true,
+        }
+    }
+
+    fn describe_keys(&self) -> String {
+        let chords: Vec<String> = self.keys.iter().map(Chord::to_string).collect();
+        chords.join(" ")
+    }
+}
+
+/// Describes why `a` and `b` can't both be used, if they can't.
+fn conflict(a: &Binding, b: &Binding) -> Option<String> {
+    if !FOCUSES.iter().any(|f| a.applies(*f) && b.applies(*f)) {
+        return None;
+    }
+    let (short, long) = if a.keys.len() <= b.keys.len() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    if short.keys == long.keys {
+        Some(format!(
+            "`{}` is bound to both `{}` and `{}`",
+            short.describe_keys(),
+            short.command,
+            long.command
+        ))
+    } else if long.keys.starts_with(&short.keys) {
+        Some(format!(
+            "`{}` ({}) is a prefix of `{}` ({}), so only one of them can be typed",
+            short.describe_keys(),
+            short.command,
+            long.describe_keys(),
+            long.command
+        ))
+    } else {
+        None
+    }
+}
+
+/// What a key press resolved to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resolution {
+    /// A binding matched; runs the named command, e.g. `move_left`.
+    Command(String),
+    /// An unbound printable key in the editor, which types its text.
+    Insert(String),
+    /// The chords so far start a longer sequence.
+    Pending,
+    Unbound,
+}
+
+/// Maps sequences of key chords to command names. Bindings earlier in the
+/// list win, so user bindings are put in front of the defaults.
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let tables = [
+            (FIND_BAR_BINDINGS, Some("find_bar_focused")),
+            (QUICK_OPEN_BINDINGS, Some("quick_open_focused")),
+            (EDITOR_BINDINGS, Some("editor_focused")),
+            (GLOBAL_BINDINGS, None),
+        ];
+        let bindings = tables
+            .iter()
+            .flat_map(|&(table, when)| {
+                table.iter().map(move |(keys, command)| {
+                    Binding::parse(keys, command, when).expect("default bindings are valid chords")
+                })
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// Puts the user's bindings in front of the defaults. Also returns a
+    /// description of each user binding that is invalid or conflicts with
+    /// another; overriding a default binding is not a conflict.
+    pub fn with_user_bindings(user: &[Keybinding]) -> (Self, Vec<String>) {
+        let mut problems = Vec::new();
+        let mut bindings = Vec::new();
+        for binding in user {
+            match Binding::parse(&binding.keys, &binding.command, binding.when.as_deref()) {
+                Ok(binding) => bindings.push(binding),
+                Err(e) => problems.push(e),
+            }
+        }
+
+        for (ix, a) in bindings.iter().enumerate() {
+            problems.extend(bindings[ix + 1..].iter().filter_map(|b| conflict(a, b)));
+        }
+
+        bindings.extend(Keymap::default().bindings);
+        (Keymap { bindings }, problems)
+    }
+
+    /// Resolves `press`, following the chords already `pending` in a sequence.
+    /// The first binding that matches or starts with the chords decides.
+    pub fn resolve(&self, pending: &[Chord], press: &KeyPress, focus: Focus) -> Resolution {
+        let mut keys = pending.to_vec();
+        keys.push(Chord::from(press));
+
+        for binding in self.bindings.iter().filter(|b| b.applies(focus)) {
+            if binding.keys == keys {
+                return Resolution::Command(binding.command.clone());
+            }
+            if binding.keys.starts_with(&keys) {
+                return Resolution::Pending;
+            }
+        }
+
+        // anywhere but the editor, text goes to whatever input has the focus
+        if pending.is_empty() && press.is_text() && focus == Focus::Editor {
+            Resolution::Insert(press.key.clone())
+        } else {
+            Resolution::Unbound
+        }
+    }
+}
+
+/// Feeds key presses through a keymap, remembering the chords of a
+/// sequence that has been started but not finished.
+pub struct KeySequence {
+    pub keymap: Keymap,
+    pub focus: Focus,
+    pending: Vec<Chord>,
+}
+
+impl KeySequence {
+    pub fn new(keymap: Keymap) -> Self {
+        KeySequence {
+            keymap,
+            focus: Focus::Editor,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn press(&mut self, press: &KeyPress) -> Resolution {
+        if MODIFIER_KEYS.contains(&press.key.as_str()) {
+            return Resolution::Unbound;
+        }
+
+        let resolution = self.keymap.resolve(&self.pending, press, self.focus);
+        if resolution == Resolution::Pending {
+            self.pending.push(Chord::from(press));
+        } else {
+            self.pending.clear();
+        }
+        resolution
+    }
+}
+
+/// Builds the `Edit` for a command that takes no arguments from its xi method name.
+pub fn edit_command(command: &str, view_id: &ViewId) -> Option<Edit> {
+    serde_json::from_value(serde_json::json!({
+        "method": command,
+        "view_id": view_id,
+    }))
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The key press the browser reports for a chord.
+    fn press(chord: &str) -> KeyPress {
+        let chord = Chord::parse(chord).unwrap();
+        KeyPress {
+            key: chord.key,
+            ctrl: chord.ctrl,
+            alt: chord.alt,
+            shift: chord.shift,
+            meta: chord.meta,
+        }
+    }
+
+    fn resolve(chord: &str, focus: Focus) -> Resolution {
+        Keymap::default().resolve(&[], &press(chord), focus)
+    }
+
+    fn command(name: &str) -> Resolution {
+        Resolution::Command(name.to_string())
+    }
+
+    fn edit(chord: &str) -> Option<Edit> {
+        match resolve(chord, Focus::Editor) {
+            Resolution::Command(command) => edit_command(&command, &"view-id-1".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn editor_keys_become_edits() {
+        assert!(matches!(edit("left"), Some(Edit::MoveLeft { .. })));
+        assert!(matches!(edit("down"), Some(Edit::MoveDown { .. })));
+        assert!(matches!(
+            edit("shift+right"),
+            Some(Edit::MoveRightAndModifySelection { .. })
+        ));
+        assert!(matches!(
+            edit("shift+up"),
+            Some(Edit::MoveUpAndModifySelection { .. })
+        ));
+        assert!(matches!(edit("ctrl+z"), Some(Edit::Undo { .. })));
+        assert!(matches!(edit("ctrl+shift+z"), Some(Edit::Redo { .. })));
+        assert!(matches!(
+            edit("shift+pagedown"),
+            Some(Edit::ScrollPageDownAndModifySelection { .. })
+        ));
+    }
+
+    #[test]
+    fn every_default_editor_command_is_an_edit() {
+        for (keys, command) in EDITOR_BINDINGS {
+            assert!(
+                edit_command(command, &"view-id-1".to_string()).is_some(),
+                "`{}` is bound to `{}`, which xi-core doesn't know",
+                keys,
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn editor_focus() {
+        assert_eq!(resolve("ctrl+s", Focus::Editor), command("save"));
+        assert_eq!(resolve("left", Focus::Editor), command("move_left"));
+        assert_eq!(
+            resolve("shift+left", Focus::Editor),
+            command("move_left_and_modify_selection")
+        );
+        assert_eq!(resolve("ctrl+z", Focus::Editor), command("undo"));
+        assert_eq!(
+            resolve("a", Focus::Editor),
+            Resolution::Insert("a".to_string())
+        );
+        assert_eq!(resolve("ctrl+q", Focus::Editor), Resolution::Unbound);
+        assert_eq!(resolve("f12", Focus::Editor), Resolution::Unbound);
+    }
+
+    #[test]
+    fn find_bar_focus() {
+        assert_eq!(resolve("ctrl+s", Focus::FindBar), command("save"));
+        assert_eq!(resolve("enter", Focus::FindBar), command("find_next"));
+        assert_eq!(resolve("escape", Focus::FindBar), command("close_find"));
+        // the find field handles these itself
+        assert_eq!(resolve("left", Focus::FindBar), Resolution::Unbound);
+        assert_eq!(resolve("shift+left", Focus::FindBar), Resolution::Unbound);
+        assert_eq!(resolve("ctrl+z", Focus::FindBar), Resolution::Unbound);
+        assert_eq!(resolve("a", Focus::FindBar), Resolution::Unbound);
+    }
+
+    #[test]
+    fn quick_open_focus() {
+        assert_eq!(resolve("ctrl+s", Focus::QuickOpen), command("save"));
+        assert_eq!(
+            resolve("up", Focus::QuickOpen),
+            command("quick_open_previous")
+        );
+        assert_eq!(
+            resolve("down", Focus::QuickOpen),
+            command("quick_open_next")
+        );
+        assert_eq!(resolve("left", Focus::QuickOpen), Resolution::Unbound);
+        assert_eq!(resolve("ctrl+z", Focus::QuickOpen), Resolution::Unbound);
+        assert_eq!(resolve("a", Focus::QuickOpen), Resolution::Unbound);
+    }
+
+    #[test]
+    fn browser_keys_match_bindings() {
+        // with shift held the browser reports the uppercase letter
+        let redo = KeyPress {
+            key: "Z".to_string(),
+            ctrl: true,
+            alt: false,
+            shift: true,
+            meta: false,
+        };
+        let keymap = Keymap::default();
+        assert_eq!(keymap.resolve(&[], &redo, Focus::Editor), command("redo"));
+        let left = KeyPress {
+            key: "ArrowLeft".to_string(),
+            ..press("x")
+        };
+        assert_eq!(
+            keymap.resolve(&[], &left, Focus::Editor),
+            command("move_left")
+        );
+    }
+
+    #[test]
+    fn modifier_keys_alone_do_nothing() {
+        let mut keys = KeySequence::new(Keymap::default());
+        let ctrl = KeyPress {
+            key: "Control".to_string(),
+            ctrl: true,
+            ..press("x")
+        };
+        assert_eq!(keys.press(&ctrl), Resolution::Unbound);
+        assert_eq!(keys.press(&press("ctrl+s")), command("save"));
+    }
+
+    fn chord(key: &str, ctrl: bool, alt: bool, shift: bool, meta: bool) -> Chord {
+        Chord {
+            key: key.to_string(),
+            ctrl,
+            alt,
+            shift,
+            meta,
+        }
+    }
+
+    fn user(keys: &str, command: &str, when: Option<&str>) -> Keybinding {
+        Keybinding {
+            keys: keys.to_string(),
+            command: command.to_string(),
+            when: when.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parses_chords() {
+        let parse = |s| Chord::parse(s).unwrap();
+        assert_eq!(parse("ctrl+shift+z"), chord("z", true, false, true, false));
+        assert_eq!(
+            parse("Ctrl+Alt+Up"),
+            chord("ArrowUp", true, true, false, false)
+        );
+        assert_eq!(parse("cmd+s"), chord("s", false, false, false, true));
+        assert_eq!(parse("PageUp"), chord("PageUp", false, false, false, false));
+        assert_eq!(parse("C-x"), chord("x", true, false, false, false));
+        assert_eq!(parse("C-M-f"), chord("f", true, true, false, false));
+        // emacs' `s-` is super and `S-` is shift
+        assert_eq!(parse("s-a"), chord("a", false, false, false, true));
+        assert_eq!(
+            parse("S-left"),
+            chord("ArrowLeft", false, false, true, false)
+        );
+        assert_eq!(parse("C-S-z"), parse("ctrl+shift+z"));
+        // a lone letter before a dash is a key, not a modifier
+        assert_eq!(parse("-"), chord("-", false, false, false, false));
+        assert_eq!(parse("ctrl+-"), chord("-", true, false, false, false));
+    }
+
+    #[test]
+    fn rejects_bad_chords() {
+        assert!(Chord::parse("hyper+x").is_err());
+        assert!(Chord::parse("ctrl+").is_err());
+        assert!(Chord::parse("").is_err());
+        assert!(Binding::parse("", "save", None).is_err());
+        assert!(Binding::parse("ctrl+s", "save", Some("sidebar_focused")).is_err());
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let find = Condition::parse("find_bar_focused").unwrap();
+        assert!(find.matches(Focus::FindBar));
+        assert!(!find.matches(Focus::Editor));
+
+        let not_editor = Condition::parse(" ! editor_focused ").unwrap();
+        assert!(!not_editor.matches(Focus::Editor));
+        assert!(not_editor.matches(Focus::FindBar));
+        assert!(not_editor.matches(Focus::QuickOpen));
+    }
+
+    #[test]
+    fn follows_emacs_sequences() {
+        let (keymap, problems) = Keymap::with_user_bindings(&[user("C-x C-s", "save", None)]);
+        assert!(problems.is_empty());
+        let mut keys = KeySequence::new(keymap);
+        assert_eq!(keys.press(&press("ctrl+x")), Resolution::Pending);
+        assert_eq!(keys.press(&press("ctrl+s")), command("save"));
+
+        // a key that doesn't continue the sequence ends it
+        assert_eq!(keys.press(&press("ctrl+x")), Resolution::Pending);
+        assert_eq!(keys.press(&press("a")), Resolution::Unbound);
+        assert_eq!(keys.press(&press("a")), Resolution::Insert("a".to_string()));
+    }
+
+    #[test]
+    fn user_bindings_win_over_defaults() {
+        let (keymap, problems) = Keymap::with_user_bindings(&[
+            user("ctrl+s", "select_all", None),
+            user("enter", "find_all", Some("find_bar_focused")),
+        ]);
+        assert!(problems.is_empty(), "{:?}", problems);
+        let resolve = |chord, focus| keymap.resolve(&[], &press(chord), focus);
+        assert_eq!(resolve("ctrl+s", Focus::Editor), command("select_all"));
+        assert_eq!(resolve("enter", Focus::FindBar), command("find_all"));
+        // the condition keeps the default elsewhere
+        assert_eq!(resolve("enter", Focus::Editor), command("insert_newline"));
+    }
+
+    #[test]
+    fn conditions_limit_bindings() {
+        let (keymap, _) = Keymap::with_user_bindings(&[user(
+            "ctrl+k",
+            "delete_to_end_of_paragraph",
+            Some("!find_bar_focused"),
+        )]);
+        let resolve = |focus| keymap.resolve(&[], &press("ctrl+k"), focus);
+        assert_eq!(
+            resolve(Focus::Editor),
+            command("delete_to_end_of_paragraph")
+        );
+        assert_eq!(
+            resolve(Focus::QuickOpen),
+            command("delete_to_end_of_paragraph")
+        );
+        assert_eq!(resolve(Focus::FindBar), Resolution::Unbound);
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let conflicts = |bindings: &[Keybinding]| Keymap::with_user_bindings(bindings).1;
+
+        let same = conflicts(&[user("ctrl+k", "undo", None), user("C-k", "redo", None)]);
+        assert_eq!(same, vec!["`ctrl+k` is bound to both `undo` and `redo`"]);
+
+        let prefix = conflicts(&[user("C-x", "cut", None), user("C-x C-s", "save", None)]);
+        assert_eq!(prefix.len(), 1);
+        assert!(prefix[0].contains("is a prefix of"), "{}", prefix[0]);
+
+        // never active at the same time
+        let apart = conflicts(&[
+            user("ctrl+k", "undo", Some("editor_focused")),
+            user("ctrl+k", "find_all", Some("find_bar_focused")),
+        ]);
+        assert!(apart.is_empty(), "{:?}", apart);
+        let negated = conflicts(&[
+            user("ctrl+k", "undo", Some("editor_focused")),
+            user("ctrl+k", "find_all", Some("!editor_focused")),
+        ]);
+        assert!(negated.is_empty(), "{:?}", negated);
+
+        // shadowing a default is what user bindings are for
+        let defaults = conflicts(&[user("ctrl+z", "redo", None), user("C-x C-s", "save", None)]);
+        assert!(defaults.is_empty(), "{:?}", defaults);
+
+        let invalid = conflicts(&[user("hyper+k", "undo", None)]);
+        assert_eq!(invalid.len(), 1);
+    }
+}
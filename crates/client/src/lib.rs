@@ -7,22 +7,37 @@ extern crate wasm_bindgen;
 extern crate yew;
 extern crate zn_core;
 
-use stdweb::web::Date;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use stdweb::web::{document, window, Date};
+use stdweb::{js, Value};
 use yew::prelude::*;
 use yew::{
-    html, services::keyboard::KeyboardService, services::ConsoleService, Component, ComponentLink,
-    Html, ShouldRender,
+    html,
+    services::keyboard::{KeyListenerHandle, KeyboardService},
+    services::ConsoleService,
+    Component, ComponentLink, Html, ShouldRender,
 };
 
 use socket::*;
 use wasm_bindgen::prelude::*;
 use zn_core::messages::*;
+use zn_core::styles::StyleTable;
 
 pub mod bus;
+pub mod find;
+pub mod keymap;
 pub mod line;
+pub mod quick_open;
 pub mod socket;
+pub mod style;
 pub mod view;
 
+use crate::find::{Find, FindMsg};
+use crate::keymap::{edit_command, Focus, KeyPress, KeySequence, Keymap, Resolution};
+use crate::quick_open::{QuickOpen, QuickOpenMsg};
 use crate::view::View;
 
 #[wasm_bindgen]
@@ -35,17 +50,131 @@ pub struct Model {
     link: ComponentLink<Self>,
     console: ConsoleService,
     socket: Box<Bridge<socket::Mediary>>,
-    keyboard: KeyboardService,
-    views: Vec<ViewId>,
+    keyboard: KeyListenerHandle,
+    keys: Rc<RefCell<KeySequence>>,
+    /// The open views in the order of their tabs.
+    views: Vec<OpenView>,
+    /// Where each view is mounted. Views keep their slot while tabs move
+    /// and close around them, so a `View` never has its id swapped, which
+    /// would throw away lines xi-core won't send again.
+    slots: Vec<Option<ViewId>>,
+    /// The view of the active tab.
+    focused: Option<ViewId>,
+    /// The index of the tab being dragged.
+    dragged_tab: Option<usize>,
+    style_table: Rc<StyleTable>,
+    themes: Vec<String>,
+    theme: Option<String>,
+    find: Find,
+    quick_open: QuickOpen,
+    /// The number of matches in each view, from `find_status`.
+    matches: HashMap<ViewId, u64>,
     value: Vec<Line>,
 }
 
+/// A view opened in the editor, and the file it is editing.
+pub struct OpenView {
+    id: ViewId,
+    file_path: Option<String>,
+    /// False once the buffer has changes that aren't saved.
+    pristine: bool,
+}
+
+impl OpenView {
+    fn title(&self) -> String {
+        self.file_path
+            .as_ref()
+            .and_then(|path| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("untitled"))
+    }
+}
+
 pub enum Msg {
     OpenFile,
     WSReceived(ServerMessage),
+    Response(Request, Result<Response, RpcError>),
+    Key(Resolution),
+    Clipboard(Clipboard),
+    Focus(ViewId),
+    Save,
+    SaveAs,
+    Close(ViewId),
+    DragTab(usize),
+    DropTab(usize),
+    SetTheme(String),
+    Find(FindMsg),
+    QuickOpen(QuickOpenMsg),
     Empty,
 }
 
+/// A clipboard event in the editor.
+pub enum Clipboard {
+    Copy,
+    Cut,
+    Paste(String),
+}
+
+/// Hands the copy, cut and paste events meant for the editor to `callback`,
+/// leaving the ones in the find bar and quick open to the browser. Like the
+/// keyboard, they are listened to on the document.
+fn listen_to_clipboard(keys: Rc<RefCell<KeySequence>>, callback: Callback<Clipboard>) {
+    let handle = move |kind: String, text: String| -> bool {
+        if keys.borrow().focus != Focus::Editor {
+            return false;
+        }
+        callback.emit(match kind.as_str() {
+            "copy" => Clipboard::Copy,
+            "cut" => Clipboard::Cut,
+            _ => Clipboard::Paste(text),
+        });
+        true
+    };
+    js! { @(no_return)
+        var handle = @{handle};
+        ["copy", "cut", "paste"].forEach(function(kind) {
+            document.addEventListener(kind, function(e) {
+                var text = kind === "paste" ? e.clipboardData.getData("text/plain") : "";
+                // what's copied comes from xi-core, and is written once it answers
+                if (handle(kind, text)) {
+                    e.preventDefault();
+                }
+            });
+        });
+    }
+}
+
+/// Puts `text` on the system clipboard.
+fn write_clipboard(text: &str) {
+    js! { @(no_return)
+        navigator.clipboard.writeText(@{text}).catch(function(e) {
+            console.warn("Failed to write the clipboard: " + e);
+        });
+    }
+}
+
+/// Asks the user for a line of text, returning `None` if they cancel.
+fn prompt(message: &str, default: &str) -> Option<String> {
+    let value: Value = js! {
+        return prompt(@{message}, @{default});
+    };
+    value.into_string()
+}
+
+/// Puts the keyboard focus in the input matching `selector`, once it's drawn.
+fn focus_input(selector: &'static str) {
+    js! { @(no_return)
+        var selector = @{selector};
+        setTimeout(function() {
+            var input = document.querySelector(selector);
+            if (input) {
+                input.focus();
+                input.select();
+            }
+        }, 0);
+    }
+}
+
 impl Component for Model {
     type Message = Msg;
     type Properties = ();
@@ -68,40 +197,263 @@ impl Component for Model {
 
         //    Msg::WSReceived(server_message)
         //});
-        let callback =
-            link.callback(|Receive::Forward(server_message)| Msg::WSReceived(server_message));
+        let callback = link.callback(|receive| match receive {
+            Receive::Forward(server_message) => Msg::WSReceived(server_message),
+            Receive::Response(request, response) => Msg::Response(request, response),
+        });
         // `Worker::bridge` spawns an instance if no one is available
         let mut socket = socket::Mediary::bridge(callback); // Connected! :tada:
         socket.send(Send::Subscribe);
 
+        let keys = Rc::new(RefCell::new(KeySequence::new(Keymap::default())));
+        let keyboard = {
+            let keys = Rc::clone(&keys);
+            let callback = link.callback(move |e: KeyDownEvent| {
+                let resolution = keys.borrow_mut().press(&KeyPress::from_event(&e));
+                if resolution != Resolution::Unbound {
+                    e.prevent_default();
+                }
+                Msg::Key(resolution)
+            });
+            KeyboardService::register_key_down(&document(), callback)
+        };
+        listen_to_clipboard(Rc::clone(&keys), link.callback(Msg::Clipboard));
+
         Model {
             link,
             console: ConsoleService::new(),
             socket: socket,
-            keyboard: KeyboardService {},
+            keyboard,
+            keys,
             views: Vec::new(),
+            slots: Vec::new(),
+            focused: None,
+            dragged_tab: None,
+            style_table: Rc::new(StyleTable::new()),
+            themes: Vec::new(),
+            theme: None,
+            find: Find::default(),
+            quick_open: QuickOpen::default(),
+            matches: HashMap::new(),
             value: Vec::new(),
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
-            Msg::WSReceived(ServerMessage::EditorNotification(Notification::Result {
-                id: _,
-                result,
-            })) => {
+            Msg::Response(Request::NewView(params), Ok(Response::NewView(view_id))) => {
                 // New View is ready, open an empty browser tab
                 self.console.log("Adding new view");
-                self.views.push(result);
+                // follow the view's updates to know when it has unsaved changes
+                self.socket.send(Send::SubscribeToView(view_id.clone()));
+                self.focused = Some(view_id.clone());
+                match self.slots.iter_mut().find(|slot| slot.is_none()) {
+                    Some(slot) => *slot = Some(view_id.clone()),
+                    None => self.slots.push(Some(view_id.clone())),
+                }
+                self.views.push(OpenView {
+                    id: view_id,
+                    file_path: params.file_path,
+                    pristine: true,
+                });
+            }
+            Msg::Response(Request::Copy(_), Ok(Response::Copy(Some(text))))
+            | Msg::Response(Request::Cut(_), Ok(Response::Cut(Some(text)))) => {
+                write_clipboard(&text);
+                return false;
+            }
+            Msg::Response(Request::ListDirectory(_), Ok(Response::ListDirectory(listing))) => {
+                self.quick_open.set_listing(listing);
+            }
+            Msg::Response(_, Err(error)) => {
+                self.console
+                    .error(&format!("Request failed: {}", error.message));
+            }
+            Msg::WSReceived(ServerMessage::ServerError { reason, .. }) => {
+                self.console.error(&format!("Server error: {}", reason));
+                return false;
+            }
+            Msg::WSReceived(ServerMessage::Connected { resumed: false, .. }) => {
+                return self.reopen_views();
+            }
+            Msg::WSReceived(ServerMessage::Keybindings { bindings }) => {
+                let (keymap, problems) = Keymap::with_user_bindings(&bindings);
+                for problem in problems {
+                    self.console
+                        .warn(&format!("Keybinding ignored: {}", problem));
+                }
+                self.keys.borrow_mut().keymap = keymap;
+                return false;
+            }
+            Msg::WSReceived(ServerMessage::OpenFiles { paths }) => {
+                for path in paths {
+                    self.socket
+                        .send(Send::Request(Request::NewView(NewViewParams {
+                            file_path: Some(path),
+                        })));
+                }
+                return false;
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::DefStyle(params))) => {
+                // styles are global, so the views get them from here
+                Rc::make_mut(&mut self.style_table).define(params);
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::MeasureWidth { id, requests })) => {
+                // xi-core waits for these, so they go back right away
+                let widths = requests
+                    .iter()
+                    .map(|request| {
+                        let look = self
+                            .style_table
+                            .get(request.id)
+                            .cloned()
+                            .unwrap_or_default();
+                        style::measure_widths(&look, &request.strings)
+                    })
+                    .collect();
+                self.socket
+                    .send(Send::Forward(ClientMessage::MeasuredWidths { id, widths }));
+                return false;
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::AvailableThemes { themes })) => {
+                self.themes = themes;
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::ThemeChanged { name, theme })) => {
+                style::apply_theme(&theme);
+                self.theme = Some(name);
             }
-            Msg::OpenFile => {
-                self.socket.send(Send::Forward(ClientMessage::NewView {
-                    id: 0,
-                    params: NewViewParams {
-                        file_path: Some(String::from("/home/nconnor/p/zn/zn/build.rs")),
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::FindStatus {
+                queries,
+                view_id,
+            })) => {
+                let matches = queries.iter().map(|query| query.matches).sum();
+                return self.matches.insert(view_id, matches) != Some(matches);
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::ReplaceStatus {
+                status, ..
+            })) => {
+                self.find.replacement = status.chars;
+            }
+            Msg::WSReceived(ServerMessage::EditorMethod(Method::Update { update, view_id })) => {
+                return match self.views.iter_mut().find(|view| view.id == view_id) {
+                    Some(view) if view.pristine != update.pristine => {
+                        view.pristine = update.pristine;
+                        true
+                    }
+                    _ => false,
+                };
+            }
+            Msg::Key(Resolution::Command(command)) => return self.run_command(&command),
+            Msg::Key(Resolution::Insert(chars)) => {
+                if let Some(view_id) = self.focused.clone() {
+                    self.socket.send(Send::Forward(ClientMessage::Edit {
+                        id: None,
+                        params: Edit::Insert {
+                            params: CharsParams { chars },
+                            view_id,
+                        },
+                    }));
+                }
+                return false;
+            }
+            Msg::Key(_) => return false,
+            Msg::Clipboard(event) => return self.clipboard(event),
+            Msg::Focus(view_id) => {
+                if self.focused.as_ref() == Some(&view_id) {
+                    return false;
+                }
+                self.focused = Some(view_id);
+            }
+            Msg::Save => {
+                let view = self
+                    .views
+                    .iter()
+                    .find(|view| Some(&view.id) == self.focused.as_ref());
+                match view.map(|view| (view.id.clone(), view.file_path.clone())) {
+                    Some((view_id, Some(file_path))) => self.save(view_id, file_path),
+                    Some((_, None)) => return self.update(Msg::SaveAs),
+                    None => {}
+                }
+                return false;
+            }
+            Msg::SaveAs => {
+                let focused = self.focused.clone();
+                let view = self
+                    .views
+                    .iter_mut()
+                    .find(|view| Some(&view.id) == focused.as_ref());
+                let (view_id, file_path) = match view {
+                    Some(view) => {
+                        let default = view.file_path.clone().unwrap_or_default();
+                        match prompt("Save as", &default) {
+                            Some(file_path) if !file_path.is_empty() => {
+                                view.file_path = Some(file_path.clone());
+                                (view.id.clone(), file_path)
+                            }
+                            _ => return false,
+                        }
+                    }
+                    None => return false,
+                };
+                self.save(view_id, file_path);
+            }
+            Msg::Close(view_id) => {
+                let view = match self.views.iter().position(|view| view.id == view_id) {
+                    Some(ix) => ix,
+                    None => return false,
+                };
+                if !self.views[view].pristine {
+                    let message = format!(
+                        "{} has unsaved changes. Close it anyway?",
+                        self.views[view].title()
+                    );
+                    if !window().confirm(&message) {
+                        return false;
+                    }
+                }
+
+                self.views.remove(view);
+                self.socket.send(Send::UnsubscribeFromView(view_id.clone()));
+                self.socket.send(Send::Forward(ClientMessage::CloseView {
+                    params: CloseViewParams {
+                        view_id: view_id.clone(),
                     },
                 }));
+                if self.focused.as_ref() == Some(&view_id) {
+                    // the tab that took its place, or the new last one
+                    let next = self.views.get(view).or_else(|| self.views.last());
+                    self.focused = next.map(|view| view.id.clone());
+                }
+                for slot in self.slots.iter_mut() {
+                    if slot.as_ref() == Some(&view_id) {
+                        *slot = None;
+                    }
+                }
+                while let Some(None) = self.slots.last() {
+                    self.slots.pop();
+                }
+                self.matches.remove(&view_id);
+            }
+            Msg::DragTab(from) => {
+                self.dragged_tab = Some(from);
+                return false;
+            }
+            Msg::DropTab(to) => {
+                return match self.dragged_tab.take() {
+                    Some(from) => self.move_tab(from, to),
+                    None => false,
+                };
+            }
+            Msg::SetTheme(theme_name) => {
+                // the new colors arrive with `theme_changed`
+                self.socket.send(Send::Forward(ClientMessage::SetTheme {
+                    params: SetThemeParams { theme_name },
+                }));
+                return false;
             }
+            Msg::Find(msg) => return self.update_find(msg),
+            Msg::QuickOpen(msg) => return self.update_quick_open(msg),
+            Msg::OpenFile => return self.update_quick_open(QuickOpenMsg::Open),
             _ => {}
         }
         true
@@ -112,17 +464,41 @@ impl Component for Model {
             <div>
                 <nav class="menu">
                     <button onclick=self.link.callback(|_| Msg::OpenFile)>
-                        { "Send New View" }
+                        { "Open…" }
                     </button>
+                    <button onclick=self.link.callback(|_| Msg::Save)>{ "Save" }</button>
+                    <button onclick=self.link.callback(|_| Msg::SaveAs)>{ "Save As" }</button>
+                    <select class="theme" onchange=self.link.callback(|e| match e {
+                        ChangeData::Select(select) => select.value().map_or(Msg::Empty, Msg::SetTheme),
+                        _ => Msg::Empty,
+                    })>
+                        {
+                            for self.themes.iter().map(|theme| html! {
+                                <option value={theme} selected={self.theme.as_ref() == Some(theme)}>{ theme }</option>
+                            })
+                        }
+                    </select>
                 </nav>
-                <div>
+                { self.view_quick_open() }
+                { self.view_find_bar() }
+                { self.view_tabs() }
+                <div class="views">
                     {
-                        for self.views.iter().map(|id| {
-                            html! {
-                                <div><span></span>
-                                <View id={id} />
-                                </div>
+                        for self.slots.iter().map(|slot| match slot {
+                            Some(view_id) => {
+                                let active = Some(view_id) == self.focused.as_ref();
+                                let class = if active {
+                                    "view-slot active"
+                                } else {
+                                    "view-slot"
+                                };
+                                html! {
+                                    <div class=class>
+                                        <View id={view_id} style_table={Rc::clone(&self.style_table)} active=active on_focus=self.link.callback(Msg::Focus) />
+                                    </div>
+                                }
                             }
+                            None => html! { <div class="view-slot"></div> },
                         })
                     }
                 </div>
@@ -131,3 +507,401 @@ impl Component for Model {
         }
     }
 }
+
+impl Model {
+    /// Runs a command from the keymap. A few act on the editor itself,
+    /// everything else is an xi edit command for the focused view.
+    fn run_command(&mut self, command: &str) -> ShouldRender {
+        match command {
+            "save" => self.update(Msg::Save),
+            "save_as" => self.update(Msg::SaveAs),
+            "close_view" => match self.focused.clone() {
+                Some(view_id) => self.update(Msg::Close(view_id)),
+                None => false,
+            },
+            "copy" => self.clipboard(Clipboard::Copy),
+            "cut" => self.clipboard(Clipboard::Cut),
+            "find" => self.update_find(FindMsg::Open { replace: false }),
+            "replace" => self.update_find(FindMsg::Open { replace: true }),
+            "close_find" => self.update_find(FindMsg::Close),
+            "find_next" => self.update_find(FindMsg::Next),
+            "find_previous" => self.update_find(FindMsg::Previous),
+            "find_all" => self.update_find(FindMsg::All),
+            "replace_next" => self.update_find(FindMsg::ReplaceNext),
+            "replace_all" => self.update_find(FindMsg::ReplaceAll),
+            "quick_open" => self.update_quick_open(QuickOpenMsg::Open),
+            "quick_open_close" => self.update_quick_open(QuickOpenMsg::Close),
+            "quick_open_next" => self.update_quick_open(QuickOpenMsg::Next),
+            "quick_open_previous" => self.update_quick_open(QuickOpenMsg::Previous),
+            "quick_open_accept" => self.update_quick_open(QuickOpenMsg::Accept),
+            "next_tab" => self.select_tab(1),
+            "previous_tab" => self.select_tab(-1),
+            "move_tab_right" | "move_tab_left" => match self.active_tab() {
+                Some(from) if command == "move_tab_right" => self.move_tab(from, from + 1),
+                Some(from) if from > 0 => self.move_tab(from, from - 1),
+                _ => false,
+            },
+            _ => {
+                if let Some(view_id) = &self.focused {
+                    match edit_command(command, view_id) {
+                        Some(params) => self
+                            .socket
+                            .send(Send::Forward(ClientMessage::Edit { id: None, params })),
+                        None => self.console.warn(&format!("Unknown command `{}`", command)),
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Copies or cuts the selection of the focused view, which xi-core
+    /// answers with the text, or pastes into it.
+    fn clipboard(&mut self, event: Clipboard) -> ShouldRender {
+        let view_id = match &self.focused {
+            Some(view_id) => view_id.clone(),
+            None => return false,
+        };
+        match event {
+            Clipboard::Copy => self.socket.send(Send::Request(Request::Copy(view_id))),
+            Clipboard::Cut => self.socket.send(Send::Request(Request::Cut(view_id))),
+            Clipboard::Paste(chars) => self.socket.send(Send::Forward(ClientMessage::Edit {
+                id: None,
+                params: Edit::Paste {
+                    params: CharsParams { chars },
+                    view_id,
+                },
+            })),
+        }
+        false
+    }
+
+    /// The index of the active tab.
+    fn active_tab(&self) -> Option<usize> {
+        self.views
+            .iter()
+            .position(|view| Some(&view.id) == self.focused.as_ref())
+    }
+
+    /// Activates the tab `offset` tabs away from the active one, wrapping
+    /// around at either end.
+    fn select_tab(&mut self, offset: isize) -> ShouldRender {
+        let count = self.views.len() as isize;
+        match self.active_tab() {
+            Some(active) if count > 1 => {
+                let next = (active as isize + offset).rem_euclid(count) as usize;
+                self.focused = Some(self.views[next].id.clone());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves the tab at `from` to `to`, shifting the ones in between.
+    fn move_tab(&mut self, from: usize, to: usize) -> ShouldRender {
+        if from == to || from >= self.views.len() || to >= self.views.len() {
+            return false;
+        }
+        let view = self.views.remove(from);
+        self.views.insert(to, view);
+        true
+    }
+
+    fn view_tabs(&self) -> Html {
+        let tabs = self.views.iter().enumerate().map(|(ix, view)| {
+            let class = if Some(&view.id) == self.focused.as_ref() {
+                "tab active"
+            } else {
+                "tab"
+            };
+            let focus = view.id.clone();
+            let close = view.id.clone();
+            html! {
+                <div class=class
+                    title=view.file_path.clone().unwrap_or_default()
+                    draggable="true"
+                    onclick=self.link.callback(move |_| Msg::Focus(focus.clone()))
+                    ondragstart=self.link.callback(move |_: DragStartEvent| Msg::DragTab(ix))
+                    ondragover=self.link.callback(|e: DragOverEvent| {
+                        // tabs only take drops when the default is prevented
+                        e.prevent_default();
+                        Msg::Empty
+                    })
+                    ondrop=self.link.callback(move |e: DragDropEvent| {
+                        e.prevent_default();
+                        Msg::DropTab(ix)
+                    })>
+                    <span>{ view.title() }</span>
+                    { if view.pristine { html! {} } else { html! { <span class="dirty">{ " ●" }</span> } } }
+                    <button class="close" onclick=self.link.callback(move |e: ClickEvent| {
+                        // don't also activate the tab being closed
+                        e.stop_propagation();
+                        Msg::Close(close.clone())
+                    })>{ "×" }</button>
+                </div>
+            }
+        });
+        html! {
+            <div class="tabs">{ for tabs }</div>
+        }
+    }
+
+    fn update_quick_open(&mut self, msg: QuickOpenMsg) -> ShouldRender {
+        match msg {
+            QuickOpenMsg::Open => {
+                self.quick_open.open = true;
+                self.quick_open.set_query(String::new());
+                // list again every time, files come and go
+                self.socket
+                    .send(Send::Request(Request::ListDirectory(ListDirectoryParams {
+                        path: String::new(),
+                        recursive: true,
+                    })));
+                focus_input(".quick-open-query");
+            }
+            QuickOpenMsg::Close => {
+                self.quick_open.open = false;
+                self.keys.borrow_mut().focus = Focus::Editor;
+            }
+            QuickOpenMsg::Query(query) => self.quick_open.set_query(query),
+            QuickOpenMsg::Next => self.quick_open.select(1),
+            QuickOpenMsg::Previous => self.quick_open.select(-1),
+            QuickOpenMsg::Accept => {
+                if let Some(path) = self.quick_open.selected_path() {
+                    self.open_file(path);
+                }
+                return self.update_quick_open(QuickOpenMsg::Close);
+            }
+            QuickOpenMsg::Pick(ix) => {
+                self.quick_open.selected = ix;
+                return self.update_quick_open(QuickOpenMsg::Accept);
+            }
+            QuickOpenMsg::Focused(focused) => {
+                self.keys.borrow_mut().focus = if focused {
+                    Focus::QuickOpen
+                } else {
+                    Focus::Editor
+                };
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Switches to the tab with `path` open, or opens it in a new one.
+    fn open_file(&mut self, path: String) {
+        let open = self
+            .views
+            .iter()
+            .find(|view| view.file_path.as_ref() == Some(&path));
+        match open {
+            Some(view) => self.focused = Some(view.id.clone()),
+            None => self
+                .socket
+                .send(Send::Request(Request::NewView(NewViewParams {
+                    file_path: Some(path),
+                }))),
+        }
+    }
+
+    /// Opens the files of the open views again after the server lost their
+    /// session, and with it xi-core's views. Unsaved changes are gone.
+    fn reopen_views(&mut self) -> ShouldRender {
+        if self.views.is_empty() {
+            return false;
+        }
+        for view in self.views.drain(..) {
+            if !view.pristine {
+                self.console
+                    .warn(&format!("Lost the unsaved changes to {}", view.title()));
+            }
+            self.socket.send(Send::UnsubscribeFromView(view.id));
+            self.socket
+                .send(Send::Request(Request::NewView(NewViewParams {
+                    file_path: view.file_path,
+                })));
+        }
+        self.slots.clear();
+        self.focused = None;
+        self.matches.clear();
+        true
+    }
+
+    fn view_quick_open(&self) -> Html {
+        if !self.quick_open.open {
+            return html! {};
+        }
+        let results = self.quick_open.matches.iter().enumerate().map(|(ix, m)| {
+            let class = if ix == self.quick_open.selected {
+                "quick-open-result selected"
+            } else {
+                "quick-open-result"
+            };
+            let chars = m.path.chars().enumerate().map(|(ix, c)| {
+                if m.positions.contains(&ix) {
+                    html! { <span class="hit">{ c }</span> }
+                } else {
+                    html! { <span>{ c }</span> }
+                }
+            });
+            html! {
+                <li class=class onclick=self.link.callback(move |_| Msg::QuickOpen(QuickOpenMsg::Pick(ix)))>
+                    { for chars }
+                </li>
+            }
+        });
+        let truncated = if self.quick_open.truncated {
+            html! { <div class="quick-open-note">{ "Only some of the workspace is listed, it has too many files" }</div> }
+        } else {
+            html! {}
+        };
+
+        html! {
+            <div class="quick-open">
+                <input class="quick-open-query"
+                    placeholder="Open a file in the workspace"
+                    value=self.quick_open.query.clone()
+                    oninput=self.link.callback(|e: InputData| Msg::QuickOpen(QuickOpenMsg::Query(e.value)))
+                    onfocus=self.link.callback(|_| Msg::QuickOpen(QuickOpenMsg::Focused(true)))
+                    onblur=self.link.callback(|_| Msg::QuickOpen(QuickOpenMsg::Focused(false))) />
+                { truncated }
+                <ul class="quick-open-results">{ for results }</ul>
+            </div>
+        }
+    }
+
+    fn update_find(&mut self, msg: FindMsg) -> ShouldRender {
+        match msg {
+            FindMsg::Open { replace } => {
+                self.find.open = true;
+                self.find.replacing = replace;
+                self.edit(|find, view_id| find.highlight(view_id, true));
+                focus_input(".find-query");
+            }
+            FindMsg::Close => {
+                self.find.open = false;
+                self.find.replacing = false;
+                self.keys.borrow_mut().focus = Focus::Editor;
+                self.edit(|find, view_id| find.highlight(view_id, false));
+            }
+            FindMsg::Query(query) => {
+                self.find.query = query;
+                self.edit(Find::find);
+                return false;
+            }
+            FindMsg::Replacement(replacement) => {
+                self.find.replacement = replacement;
+                return false;
+            }
+            FindMsg::ToggleCaseSensitive => {
+                self.find.case_sensitive = !self.find.case_sensitive;
+                self.edit(Find::find);
+            }
+            FindMsg::ToggleRegex => {
+                self.find.regex = !self.find.regex;
+                self.edit(Find::find);
+            }
+            FindMsg::ToggleWholeWords => {
+                self.find.whole_words = !self.find.whole_words;
+                self.edit(Find::find);
+            }
+            FindMsg::Next => self.edit(|find, view_id| find.find_next(view_id, true)),
+            FindMsg::Previous => self.edit(|find, view_id| find.find_next(view_id, false)),
+            FindMsg::All => self.edit(|_, view_id| Edit::FindAll {
+                view_id: view_id.clone(),
+            }),
+            FindMsg::ReplaceNext => {
+                self.edit(Find::replace);
+                self.edit(|_, view_id| Edit::ReplaceNext {
+                    view_id: view_id.clone(),
+                });
+            }
+            FindMsg::ReplaceAll => {
+                self.edit(Find::replace);
+                self.edit(|_, view_id| Edit::ReplaceAll {
+                    view_id: view_id.clone(),
+                });
+            }
+            FindMsg::Focused(focused) => {
+                self.keys.borrow_mut().focus = if focused {
+                    Focus::FindBar
+                } else {
+                    Focus::Editor
+                };
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Sends the edit `build` makes for the focused view, if there is one.
+    fn edit<F>(&mut self, build: F)
+    where
+        F: FnOnce(&Find, &ViewId) -> Edit,
+    {
+        if let Some(view_id) = &self.focused {
+            let params = build(&self.find, view_id);
+            self.socket
+                .send(Send::Forward(ClientMessage::Edit { id: None, params }));
+        }
+    }
+
+    fn view_find_bar(&self) -> Html {
+        if !self.find.open {
+            return html! {};
+        }
+        let toggle = |on: bool| if on { "toggle on" } else { "toggle" };
+        let matches = match self.focused.as_ref().and_then(|id| self.matches.get(id)) {
+            Some(1) => String::from("1 match"),
+            Some(n) => format!("{} matches", n),
+            None => String::new(),
+        };
+        let replace_row = if self.find.replacing {
+            html! {
+                <div class="find-row">
+                    <input class="find-replacement"
+                        placeholder="Replace"
+                        value=self.find.replacement.clone()
+                        oninput=self.link.callback(|e: InputData| Msg::Find(FindMsg::Replacement(e.value)))
+                        onfocus=self.link.callback(|_| Msg::Find(FindMsg::Focused(true)))
+                        onblur=self.link.callback(|_| Msg::Find(FindMsg::Focused(false))) />
+                    <button onclick=self.link.callback(|_| Msg::Find(FindMsg::ReplaceNext))>{ "Replace" }</button>
+                    <button onclick=self.link.callback(|_| Msg::Find(FindMsg::ReplaceAll))>{ "Replace All" }</button>
+                </div>
+            }
+        } else {
+            html! {}
+        };
+
+        html! {
+            <div class="find-bar">
+                <div class="find-row">
+                    <input class="find-query"
+                        placeholder="Find"
+                        value=self.find.query.clone()
+                        oninput=self.link.callback(|e: InputData| Msg::Find(FindMsg::Query(e.value)))
+                        onfocus=self.link.callback(|_| Msg::Find(FindMsg::Focused(true)))
+                        onblur=self.link.callback(|_| Msg::Find(FindMsg::Focused(false))) />
+                    <button class=toggle(self.find.case_sensitive) title="Match case"
+                        onclick=self.link.callback(|_| Msg::Find(FindMsg::ToggleCaseSensitive))>{ "Aa" }</button>
+                    <button class=toggle(self.find.whole_words) title="Match whole words"
+                        onclick=self.link.callback(|_| Msg::Find(FindMsg::ToggleWholeWords))>{ "ab" }</button>
+                    <button class=toggle(self.find.regex) title="Use regular expressions"
+                        onclick=self.link.callback(|_| Msg::Find(FindMsg::ToggleRegex))>{ ".*" }</button>
+                    <span class="find-count">{ matches }</span>
+                    <button title="Previous match" onclick=self.link.callback(|_| Msg::Find(FindMsg::Previous))>{ "↑" }</button>
+                    <button title="Next match" onclick=self.link.callback(|_| Msg::Find(FindMsg::Next))>{ "↓" }</button>
+                    <button title="Select all matches" onclick=self.link.callback(|_| Msg::Find(FindMsg::All))>{ "All" }</button>
+                    <button title="Close" onclick=self.link.callback(|_| Msg::Find(FindMsg::Close))>{ "×" }</button>
+                </div>
+                { replace_row }
+            </div>
+        }
+    }
+
+    fn save(&mut self, view_id: ViewId, file_path: String) {
+        self.socket.send(Send::Forward(ClientMessage::Save {
+            params: SaveParams { view_id, file_path },
+        }));
+    }
+}
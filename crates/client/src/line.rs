@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::rc::Rc;
 use yew::{
     prelude::*, services::ConsoleService, virtual_dom::VNode, Bridge, Callback, Component,
     ComponentLink,
@@ -6,34 +7,156 @@ use yew::{
 
 use bus;
 use bus::LineBus;
+use stdweb::web::event::{IMouseEvent, IUiEvent, MouseButton};
 use stdweb::{js, unstable::TryInto, Value};
+use style;
+use zn_core::styles::StyleTable;
 
 pub struct Line {
     id: u64,
+    ln: Option<u64>,
 
     link: ComponentLink<Self>,
     linebus: Box<Bridge<LineBus>>,
     console: ConsoleService,
     text: String,
+    styles: Vec<i64>,
+    style_table: Rc<StyleTable>,
     cursor: Option<Vec<u64>>,
-    pub on_custom_fn: Callback<(u64, u64)>,
+    highlights: Vec<Highlight>,
+    pub on_pointer: Callback<(u64, u64, Pointer)>,
 }
 
-impl Line {}
+/// Ranges of a line an annotation marks, drawn behind the code with `class`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Highlight {
+    pub class: String,
+    /// Byte ranges into the line's text.
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// Splits `text` at the byte ranges in `marks`, flagging the runs inside one.
+/// An empty range gives an empty marked run, which is how carets are drawn.
+/// Ranges that overlap an earlier one or split a character are skipped.
+fn mark_runs<'a>(text: &'a str, marks: &[(usize, usize)]) -> Vec<(&'a str, bool)> {
+    let mut marks = marks.to_vec();
+    marks.sort();
+    let mut runs = Vec::new();
+    let mut end = 0;
+    for (start, mark_end) in marks {
+        if start < end
+            || mark_end < start
+            || !text.is_char_boundary(start)
+            || !text.is_char_boundary(mark_end)
+        {
+            continue;
+        }
+        runs.push((&text[end..start], false));
+        runs.push((&text[start..mark_end], true));
+        end = mark_end;
+    }
+    runs.push((&text[end..], false));
+    runs
+}
+
+/// A mouse interaction with a line, reported to the parent along with
+/// the line number and the column under the pointer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pointer {
+    Down {
+        click_count: i32,
+        /// Ctrl (or Cmd) was held, so the selection is added to the existing ones.
+        toggle: bool,
+        /// Shift was held, so the selection is extended to the pointer.
+        extend: bool,
+    },
+    Move,
+    Up,
+}
+
+/// Finds the caret under the given client coordinates, as the index of the
+/// run of the line's code it is in and its offset into that run, in UTF-16
+/// code units as the DOM counts them.
+fn caret_position(client_x: i32, client_y: i32) -> (usize, u64) {
+    let position: Value = js! {
+        let pageX = @{client_x};
+        let pageY = @{client_y};
+        var range;
+        var node;
+        var offset;
+
+        if (document.caretPositionFromPoint) {    // standard
+            range = document.caretPositionFromPoint(pageX, pageY);
+            node = range && range.offsetNode;
+            offset = range && range.offset;
+
+        } else if (document.caretRangeFromPoint) {    // WebKit
+            range = document.caretRangeFromPoint(pageX, pageY);
+            node = range && range.startContainer;
+            offset = range && range.startOffset;
+        }
+        if (!node) {
+            return [0, 0];
+        }
+        // past the end of the text, the offset counts the runs before it
+        if (node.classList && node.classList.contains("code")) {
+            return [offset, 0];
+        }
+        // the offset is into the text of one run, so find which
+        while (node.parentNode && !(node.parentNode.classList && node.parentNode.classList.contains("code"))) {
+            node = node.parentNode;
+        }
+        var run = 0;
+        for (var sibling = node.previousSibling; sibling; sibling = sibling.previousSibling) {
+            run += 1;
+        }
+        return [run, offset];
+    };
+
+    let position: Vec<f64> = position.try_into().unwrap_or_default();
+    match position[..] {
+        [run, offset] => (run as usize, offset as u64),
+        _ => (0, 0),
+    }
+}
+
+/// The byte column xi-core knows a caret by, from its `offset` in UTF-16
+/// code units into the run at `run` of `runs`, the texts the line's code is
+/// drawn in.
+fn caret_column(runs: &[&str], run: usize, offset: u64) -> u64 {
+    let before: usize = runs.iter().take(run).map(|text| text.len()).sum();
+    let text = runs.get(run).cloned().unwrap_or("");
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= offset {
+            return (before + index) as u64;
+        }
+        units += c.len_utf16() as u64;
+    }
+    (before + text.len()) as u64
+}
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Props {
+    /// The index of the line in its view.
     pub id: u64,
+    pub ln: Option<u64>,
     pub text: String,
+    /// The line's style spans, looked up in `style_table`.
+    pub styles: Vec<i64>,
+    pub style_table: Rc<StyleTable>,
     pub cursor: Option<Vec<u64>>,
+    pub highlights: Vec<Highlight>,
     #[props(required)]
-    pub on_custom_fn: Callback<(u64, u64)>,
+    pub on_pointer: Callback<(u64, u64, Pointer)>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub enum Message {
     Event(bus::Event),
-    Test(u64),
+    /// The run and offset of the caret under the pointer.
+    Pointer(usize, u64, Pointer),
+    Nothing,
 }
 
 impl Component for Line {
@@ -48,74 +171,183 @@ impl Component for Line {
 
         Self {
             id: props.id,
+            ln: props.ln,
             link,
             linebus: agent,
             console: ConsoleService::new(),
             text: props.text,
+            styles: props.styles,
+            style_table: props.style_table,
             cursor: props.cursor,
-            on_custom_fn: props.on_custom_fn,
+            highlights: props.highlights,
+            on_pointer: props.on_pointer,
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> bool {
-        match msg {
-            Message::Test(_0) => {
-                self.on_custom_fn.emit((_0, self.id));
-            }
-            _ => {}
+        if let Message::Pointer(run, offset, pointer) = msg {
+            let runs: Vec<&str> = self
+                .style_table
+                .segments(&self.text, &self.styles)
+                .into_iter()
+                .map(|(text, _)| text)
+                .collect();
+            let col = caret_column(&runs, run, offset);
+            self.on_pointer.emit((self.id, col, pointer));
         }
+        // the line only changes with its props
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.id = props.id;
+        self.ln = props.ln;
+        self.text = props.text;
+        self.styles = props.styles;
+        self.style_table = props.style_table;
+        self.cursor = props.cursor;
+        self.highlights = props.highlights;
+        self.on_pointer = props.on_pointer;
         true
     }
 
     fn view(&self) -> VNode {
-        let cursor_text = self.text.clone();
-        let text_node = match &self.cursor {
-            Some(positions) => {
-                let (start, end) = cursor_text.split_at((*positions.first().unwrap()) as usize);
-                html! {
-                    <span><span>{start}</span><span class="cursor"/><span>{end}</span></span>
+        let carets: Vec<_> = self
+            .cursor
+            .iter()
+            .flatten()
+            .map(|&col| (col as usize, col as usize))
+            .collect();
+        let cursors = mark_runs(&self.text, &carets)
+            .into_iter()
+            .map(|(text, caret)| {
+                if caret {
+                    html! { <span class="cursor"/> }
+                } else {
+                    html! { <span>{text}</span> }
                 }
-            }
-            None => html! {
-                <span>{cursor_text}</span>
-            },
+            });
+
+        let highlights = self.highlights.iter().map(|highlight| {
+            let runs = mark_runs(&self.text, &highlight.spans)
+                .into_iter()
+                .map(|(text, marked)| {
+                    let class = if marked { highlight.class.as_str() } else { "" };
+                    html! { <span class=class>{text}</span> }
+                });
+            html! { <div class="highlights">{ for runs }</div> }
+        });
+
+        let code = self
+            .style_table
+            .segments(&self.text, &self.styles)
+            .into_iter()
+            .map(|(text, look)| html! { <span style={style::css(&look)}>{text}</span> });
+
+        // the line with the caret gets the theme's line highlight
+        let class = if self.cursor.is_some() {
+            "line current"
+        } else {
+            "line"
         };
 
-        let on_click = self.link.callback(|e: ClickEvent| {
-            let page_x = e.client_x();
-            let page_y = e.client_y();
-
-            let offset: Value = js! {
-                let pageX = @{page_x};
-                let pageY = @{page_y};
-                var range;
-                var textNode;
-                var offset;
-
-                if (document.caretPositionFromPoint) {    // standardhttps://uploads-ssl.webflow.com/5929dd19f82ea71d6234fa2d/59efc0274a0dc400019b36cd_shaw-0617-p-500.jpeg
-                    range = document.caretPositionFromPoint(pageX, pageY);
-                    textNode = range.offsetNode;
-                    offset = range.offset;
-
-                } else if (document.caretRangeFromPoint) {    // WebKit
-                    range = document.caretRangeFromPoint(pageX, pageY);
-                    textNode = range.startContainer;
-                    offset = range.startOffset;
-                }
+        let on_mouse_down = self.link.callback(|e: MouseDownEvent| {
+            let (run, offset) = caret_position(e.client_x(), e.client_y());
+            Message::Pointer(
+                run,
+                offset,
+                Pointer::Down {
+                    click_count: e.detail(),
+                    toggle: e.ctrl_key() || e.meta_key(),
+                    extend: e.shift_key(),
+                },
+            )
+        });
 
-                return offset;
-            };
+        let on_mouse_move = self.link.callback(|e: MouseMoveEvent| {
+            // only report moves while the primary button is held
+            if e.buttons().is_down(MouseButton::Left) {
+                let (run, offset) = caret_position(e.client_x(), e.client_y());
+                Message::Pointer(run, offset, Pointer::Move)
+            } else {
+                Message::Nothing
+            }
+        });
 
-            let offset: u64 = offset.try_into().unwrap();
-            Message::Test(offset)
+        let on_mouse_up = self.link.callback(|e: MouseUpEvent| {
+            let (run, offset) = caret_position(e.client_x(), e.client_y());
+            Message::Pointer(run, offset, Pointer::Up)
         });
 
         html! {
-            <div class="line">
-               <div class="gutter">{self.id.clone()}</div>
-               <div class="cursors">{text_node.clone()}</div>
-               <div class="code" onclick={on_click}>{text_node}</div>
+            <div class=class>
+               <div class="gutter">{self.ln.map(|ln| ln.to_string()).unwrap_or_default()}</div>
+               { for highlights }
+               <div class="cursors">{ for cursors }</div>
+               <div class="code"
+                    onmousedown={on_mouse_down}
+                    onmousemove={on_mouse_move}
+                    onmouseup={on_mouse_up}>{ for code }</div>
             </div>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zn_core::messages::DefStyleParams;
+
+    fn style_table(ids: &[u64]) -> StyleTable {
+        let mut table = StyleTable::new();
+        for &id in ids {
+            table.define(DefStyleParams {
+                id,
+                fg_color: Some(id as u32),
+                bg_color: None,
+                weight: None,
+                italic: None,
+                underline: None,
+            });
+        }
+        table
+    }
+
+    #[test]
+    fn caret_columns_count_the_runs_before() {
+        // `let é = "日本";` with `let` and the string styled
+        let text = "let é = \"日本\";";
+        let table = style_table(&[2, 3]);
+        let runs: Vec<&str> = table
+            .segments(text, &[0, 3, 2, 6, 8, 3])
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(runs, vec!["let", " é = ", "\"日本\"", ";"]);
+
+        assert_eq!(caret_column(&runs, 0, 2), 2);
+        // after `é`, one UTF-16 unit but two bytes
+        assert_eq!(caret_column(&runs, 1, 2), 6);
+        // between `日` and `本`, three bytes each
+        assert_eq!(caret_column(&runs, 2, 2), 13);
+        assert_eq!(caret_column(&runs, 3, 0), 17);
+        assert_eq!(caret_column(&runs, 3, 1), text.len() as u64);
+    }
+
+    #[test]
+    fn caret_columns_past_the_end_are_the_end_of_the_line() {
+        let runs = vec!["fn", " main"];
+        assert_eq!(caret_column(&runs, 2, 0), 7);
+        assert_eq!(caret_column(&runs, 1, 40), 7);
+        assert_eq!(caret_column(&[], 0, 0), 0);
+    }
+
+    #[test]
+    fn caret_columns_count_surrogate_pairs_as_one_character() {
+        // 🦀 is two UTF-16 units and four bytes
+        let runs = vec!["a🦀b"];
+        assert_eq!(caret_column(&runs, 0, 1), 1);
+        assert_eq!(caret_column(&runs, 0, 3), 5);
+        assert_eq!(caret_column(&runs, 0, 4), 6);
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::Path;
+use zn_core::messages::{DirectoryListing, FileKind};
+
+/// How many matches the dialog shows.
+const MAX_RESULTS: usize = 50;
+
+pub enum QuickOpenMsg {
+    Open,
+    Close,
+    Query(String),
+    Next,
+    Previous,
+    /// Opens the selected match.
+    Accept,
+    /// Opens the match at this index, when it's clicked.
+    Pick(usize),
+    /// The query field gained or lost the keyboard focus.
+    Focused(bool),
+}
+
+/// A file matching the query.
+pub struct Match {
+    pub path: String,
+    /// The indices of the chars of `path` that matched the query.
+    pub positions: Vec<usize>,
+    score: i64,
+}
+
+/// The state of the quick open dialog, which fuzzy finds files in the
+/// server's workspace.
+#[derive(Default)]
+pub struct QuickOpen {
+    pub open: bool,
+    pub query: String,
+    /// The workspace the files are listed from.
+    root: String,
+    /// Every file in the workspace, relative to `root`.
+    files: Vec<String>,
+    /// The workspace has more files than the server lists.
+    pub truncated: bool,
+    pub matches: Vec<Match>,
+    pub selected: usize,
+}
+
+impl QuickOpen {
+    pub fn set_listing(&mut self, listing: DirectoryListing) {
+        self.root = listing.path;
+        self.files = listing
+            .entries
+            .into_iter()
+            .filter(|entry| entry.kind == FileKind::File)
+            .map(|entry| entry.path)
+            .collect();
+        self.truncated = listing.truncated;
+        self.refresh();
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.refresh();
+    }
+
+    /// Moves the selection by `offset` matches, wrapping around.
+    pub fn select(&mut self, offset: isize) {
+        let count = self.matches.len() as isize;
+        if count > 0 {
+            self.selected = (self.selected as isize + offset).rem_euclid(count) as usize;
+        }
+    }
+
+    /// The full path of the selected match.
+    pub fn selected_path(&self) -> Option<String> {
+        self.matches.get(self.selected).map(|m| {
+            Path::new(&self.root)
+                .join(&m.path)
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+
+    fn refresh(&mut self) {
+        let query = &self.query;
+        let mut matches: Vec<Match> = self
+            .files
+            .iter()
+            .filter_map(|path| {
+                fuzzy_match(query, path).map(|(score, positions)| Match {
+                    path: path.clone(),
+                    positions,
+                    score,
+                })
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.path.len().cmp(&b.path.len()))
+        });
+        matches.truncate(MAX_RESULTS);
+        self.matches = matches;
+        self.selected = 0;
+    }
+}
+
+/// One char for one char, unlike `char::to_lowercase`, so indices line up.
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Whether `c` starts a new word in a path, after a separator or at a
+/// lowercase to uppercase change.
+fn starts_word(prev: Option<char>, c: char) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => "/\\_-. ".contains(prev) || (prev.is_lowercase() && c.is_uppercase()),
+    }
+}
+
+/// Matches `query` as a case insensitive subsequence of `candidate`. Returns
+/// a score, higher for matches that are consecutive, start words or fall in
+/// the file name, and the char indices that matched.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<(i64, Vec<usize>)> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(lowercase)
+        .collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = chars.iter().cloned().map(lowercase).collect();
+    if query.is_empty() {
+        return Some((0, Vec::new()));
+    }
+
+    // find where the earliest match ends, then walk back from there for the
+    // tightest one, which tends to land in the file name
+    let mut end = 0;
+    for &qc in &query {
+        end += lower[end..].iter().position(|&c| c == qc)? + 1;
+    }
+    let mut positions = Vec::with_capacity(query.len());
+    let mut ix = end;
+    for &qc in query.iter().rev() {
+        ix = lower[..ix].iter().rposition(|&c| c == qc)?;
+        positions.push(ix);
+    }
+    positions.reverse();
+
+    let file_name_start = chars
+        .iter()
+        .rposition(|&c| c == '/' || c == '\\')
+        .map_or(0, |ix| ix + 1);
+    let mut score = 0;
+    let mut last = None;
+    for &ix in &positions {
+        score += 1;
+        if last.map(|last| last + 1) == Some(ix) {
+            score += 5;
+        }
+        let prev = if ix == 0 { None } else { Some(chars[ix - 1]) };
+        if starts_word(prev, chars[ix]) {
+            score += 8;
+        }
+        if ix >= file_name_start {
+            score += 2;
+        }
+        last = Some(ix);
+    }
+    // spread out matches are worse
+    score -= (positions[positions.len() - 1] - positions[0]) as i64 / 4;
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zn_core::messages::FileStat;
+
+    fn score(query: &str, candidate: &str) -> i64 {
+        fuzzy_match(query, candidate).unwrap().0
+    }
+
+    fn positions(query: &str, candidate: &str) -> Vec<usize> {
+        fuzzy_match(query, candidate).unwrap().1
+    }
+
+    fn listing(paths: &[&str]) -> DirectoryListing {
+        let file = |path: &&str| FileStat {
+            path: path.to_string(),
+            kind: FileKind::File,
+            size: 0,
+            modified: None,
+        };
+        DirectoryListing {
+            path: String::from("/work"),
+            entries: paths.iter().map(file).collect(),
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn matches_subsequences() {
+        assert_eq!(positions("lib", "src/lib.rs"), vec![4, 5, 6]);
+        assert_eq!(positions("slr", "src/lib.rs"), vec![0, 4, 8]);
+        assert_eq!(fuzzy_match("bil", "src/lib.rs"), None);
+        assert_eq!(fuzzy_match("libs", "lib.r"), None);
+    }
+
+    #[test]
+    fn empty_queries_match_everything() {
+        assert_eq!(fuzzy_match("", "src/lib.rs"), Some((0, Vec::new())));
+        assert_eq!(fuzzy_match("  ", "src/lib.rs"), Some((0, Vec::new())));
+    }
+
+    #[test]
+    fn ignores_case_and_whitespace() {
+        assert_eq!(positions("LIB", "src/lib.rs"), vec![4, 5, 6]);
+        assert_eq!(positions("lib", "src/Lib.rs"), vec![4, 5, 6]);
+        assert_eq!(positions("l ib", "src/lib.rs"), vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn prefers_the_tightest_match() {
+        assert_eq!(positions("ab", "a/xab"), vec![3, 4]);
+    }
+
+    #[test]
+    fn word_starts_score_higher() {
+        assert!(score("fb", "foo_bar") > score("fb", "fooxbar"));
+        assert!(score("fb", "fooBar") > score("fb", "foobar"));
+        assert!(score("fb", "foo/bar") > score("fb", "foo/obar"));
+    }
+
+    #[test]
+    fn consecutive_chars_score_higher() {
+        assert!(score("ma", "xmain") > score("ma", "xmxa"));
+    }
+
+    #[test]
+    fn file_names_score_higher() {
+        assert!(score("main", "x/main.rs") > score("main", "main/x.rs"));
+    }
+
+    #[test]
+    fn results_are_ordered_by_score_then_length() {
+        let mut quick_open = QuickOpen::default();
+        quick_open.set_listing(listing(&[
+            "lib/mod.rs",
+            "src/lib.rs",
+            "README.md",
+            "lib.rs",
+            "a/lxixb.rs",
+        ]));
+        quick_open.set_query(String::from("lib"));
+        let paths: Vec<_> = quick_open.matches.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["lib.rs", "src/lib.rs", "lib/mod.rs", "a/lxixb.rs"]
+        );
+        assert_eq!(quick_open.selected_path().unwrap(), "/work/lib.rs");
+    }
+}
@@ -1,38 +1,103 @@
 use serde::{Deserialize, Serialize};
-use yew::format::Json;
+use std::cmp;
+use std::collections::HashMap;
+use std::time::Duration;
+use stdweb::{js, Value};
+use yew::format::{Binary, Json, Text};
+use yew::services::timeout::TimeoutTask;
 use yew::services::websocket::{WebSocketStatus, WebSocketTask};
-use yew::services::{ConsoleService, WebSocketService};
+use yew::services::{ConsoleService, TimeoutService, WebSocketService};
 use yew::worker::*;
-use zn_core::messages::{ClientMessage, ClientStartedParams, Method, ServerMessage, ViewId};
+use zn_core::messages::{
+    ClientMessage, ClientStartedParams, Encoding, Notification, Request, RequestId, Response,
+    ResumeParams, RpcError, ServerMessage, ViewId,
+};
 
-pub struct ViewSubscriber {
-    handler_id: HandlerId,
-    view_id: ViewId,
-}
+/// How long to wait before the first reconnect, doubled with each one that
+/// fails.
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+/// The longest wait between reconnects.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
 
 #[derive(Serialize, Deserialize)]
 pub enum Send {
     Subscribe,
     SubscribeToView(ViewId),
+    UnsubscribeFromView(ViewId),
     Forward(ClientMessage),
+    /// Sends a request to xi-core; its response goes back to the sender only.
+    Request(Request),
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum Receive {
     Forward(ServerMessage),
+    /// The response to a request, along with the request itself.
+    Response(Request, Result<Response, RpcError>),
+}
+
+/// A request waiting on its response, and who to deliver it to.
+struct Pending {
+    handler_id: HandlerId,
+    request: Request,
 }
 
 pub struct Mediary {
     link: AgentLink<Mediary>,
-    task: WebSocketTask,
+    socket_service: WebSocketService,
+    /// The connection to the server, if there is one.
+    task: Option<WebSocketTask>,
+    timeout: TimeoutService,
+    /// The reconnect waiting to happen, while disconnected.
+    reconnect: Option<TimeoutTask>,
+    /// The reconnects tried since the last connection opened.
+    retries: u32,
+    /// The server's session, which a new connection resumes.
+    session: Option<ResumeParams>,
+    /// How messages to the server are encoded. Stays `Json` until the
+    /// server agrees to something else in `connected`.
+    encoding: Encoding,
     console: ConsoleService,
-    subscriber: Option<HandlerId>,
-    view_subscribers: Vec<ViewSubscriber>,
+    subscribers: Vec<HandlerId>,
+    view_subscribers: HashMap<ViewId, Vec<HandlerId>>,
+    next_request_id: u64,
+    pending: HashMap<RequestId, Pending>,
 }
 
 pub enum Callback {
     Receive(ServerMessage),
+    /// A frame that isn't a `ServerMessage`.
+    Malformed(failure::Error),
     Status(WebSocketStatus),
+    Reconnect,
+}
+
+/// A frame from the server, JSON in a text frame or CBOR in a binary one.
+struct Frame(Result<ServerMessage, failure::Error>);
+
+impl From<Text> for Frame {
+    fn from(text: Text) -> Self {
+        Frame(text.and_then(|text| ServerMessage::from_json(&text).map_err(failure::Error::from)))
+    }
+}
+
+impl From<Binary> for Frame {
+    fn from(binary: Binary) -> Self {
+        Frame(
+            binary
+                .and_then(|bytes| ServerMessage::from_binary(&bytes).map_err(failure::Error::from)),
+        )
+    }
+}
+
+/// The websocket endpoint of the zn server, which sets `ZN_ENDPOINT` in the
+/// page it serves. A page hosted elsewhere can set it to point at one, and
+/// without it the server serving the page is assumed.
+fn endpoint() -> String {
+    let endpoint: Value = js! {
+        return window.ZN_ENDPOINT || ("ws://" + window.location.host + "/ws/");
+    };
+    endpoint.into_string().unwrap_or_default()
 }
 
 impl Agent for Mediary {
@@ -52,29 +117,36 @@ impl Agent for Mediary {
     //    self.handlers.push(_id)
     //}
 
+    // Called when a bridge is dropped, e.g. when its component is destroyed
+    fn disconnected(&mut self, id: HandlerId) {
+        self.subscribers.retain(|h| *h != id);
+        for handlers in self.view_subscribers.values_mut() {
+            handlers.retain(|h| *h != id);
+        }
+        self.view_subscribers
+            .retain(|_, handlers| !handlers.is_empty());
+        self.pending.retain(|_, pending| pending.handler_id != id);
+    }
+
     // Create an instance with a link to the agent.
     fn create(link: AgentLink<Self>) -> Self {
-        let receive = link.callback(|Json::<Result<ServerMessage, failure::Error>>(data)| {
-            //self.console.log(format!("Received cbout {:?}", &data).as_str());
-            //let msg_txt = data.expect("Message parses as json, then ServerMessage");
-            Callback::Receive(data.unwrap())
-        });
-
-        let send = link.callback(|input| Callback::Status(input));
-
-        let mut socket_service = WebSocketService::new();
-
-        if let Ok(task) = socket_service.connect("ws://127.0.0.1:8080/ws/", receive, send.into()) {
-            Mediary {
-                link,
-                task,
-                console: ConsoleService::new(),
-                subscriber: None,
-                view_subscribers: Vec::new(),
-            }
-        } else {
-            panic!("Socket service couldn't connect to the server!")
-        }
+        let mut mediary = Mediary {
+            link,
+            socket_service: WebSocketService::new(),
+            task: None,
+            timeout: TimeoutService::new(),
+            reconnect: None,
+            retries: 0,
+            session: None,
+            encoding: Encoding::Json,
+            console: ConsoleService::new(),
+            subscribers: Vec::new(),
+            view_subscribers: HashMap::new(),
+            next_request_id: 0,
+            pending: HashMap::new(),
+        };
+        mediary.connect();
+        mediary
     }
 
     // Handle inner messages (from callbacks)
@@ -86,37 +158,88 @@ impl Agent for Mediary {
                     .log(&format!("Socket service received: {:?}", server_message));
                 //self.handlers.iter().for_each(|h| self.link.respond(*h, server_message.clone()));
                 match server_message {
-                    ServerMessage::EditorMethod(Method::Update { update, view_id }) => {
-                        if let Some(subscriber) =
-                            self.view_subscribers.iter().find(|s| s.view_id == view_id)
-                        {
-                            self.link.respond(
-                                subscriber.handler_id,
-                                Receive::Forward(ServerMessage::EditorMethod(Method::Update {
-                                    update,
-                                    view_id,
-                                })),
-                            );
-                        }
+                    ServerMessage::Connected {
+                        session_id,
+                        encoding,
+                        ..
+                    } => {
+                        self.session = Some(ResumeParams {
+                            session_id,
+                            encoding: None,
+                        });
+                        self.encoding = encoding;
+                        self.forward(Some(&self.subscribers), server_message);
                     }
-                    _ => {
-                        if let Some(subscriber) = self.subscriber {
-                            self.link
-                                .respond(subscriber, Receive::Forward(server_message))
+                    ServerMessage::EditorMethod(method) => match method.view_id().cloned() {
+                        Some(view_id) => {
+                            let handlers = self.view_subscribers.get(&view_id);
+                            self.forward(handlers, ServerMessage::EditorMethod(method));
+                        }
+                        None => {
+                            let handlers = Some(&self.subscribers);
+                            self.forward(handlers, ServerMessage::EditorMethod(method));
                         }
+                    },
+                    ServerMessage::Presence {
+                        view_id,
+                        annotations,
+                    } => {
+                        let handlers = self.view_subscribers.get(&view_id);
+                        let presence = ServerMessage::Presence {
+                            view_id,
+                            annotations,
+                        };
+                        self.forward(handlers, presence);
+                    }
+                    ServerMessage::EditorNotification(Notification::Result { id, result }) => {
+                        self.resolve(id, |request| {
+                            request.decode(result).map_err(|e| RpcError {
+                                code: 0,
+                                message: format!("Malformed response: {}", e),
+                                data: None,
+                            })
+                        });
                     }
+                    ServerMessage::EditorNotification(Notification::Error { id, error }) => {
+                        self.resolve(id, |_| Err(error));
+                    }
+                    _ => self.forward(Some(&self.subscribers), server_message),
                 }
             }
+            Callback::Malformed(e) => {
+                self.console
+                    .error(&format!("Dropping a malformed frame: {}", e));
+            }
             Callback::Status(WebSocketStatus::Opened) => {
-                self.task.send(Json(&ClientMessage::ClientStarted {
-                    params: ClientStartedParams {
-                        client_extras_dir: None,
-                        config_dir: None,
+                self.retries = 0;
+                // the hello goes out as JSON, which every server reads
+                self.encoding = Encoding::Json;
+                let encoding = Some(Encoding::Cbor);
+                // a session that's gone is answered with a new one
+                let hello = match self.session.clone() {
+                    Some(params) => ClientMessage::Resume {
+                        params: ResumeParams { encoding, ..params },
+                    },
+                    None => ClientMessage::ClientStarted {
+                        params: ClientStartedParams {
+                            client_extras_dir: None,
+                            config_dir: None,
+                            encoding,
+                        },
                     },
-                }));
+                };
+                self.send(&hello);
             }
             Callback::Status(status) => {
                 self.console.log(&format!("Socket status: {:?}", status));
+                // an error is followed by a close, only the first one counts
+                if self.task.take().is_some() {
+                    self.disconnected_from_server();
+                }
+            }
+            Callback::Reconnect => {
+                self.reconnect = None;
+                self.connect();
             }
         }
     }
@@ -124,12 +247,138 @@ impl Agent for Mediary {
     // Handle incoming messages from components of other agents.
     fn handle_input(&mut self, msg: Self::Input, who: HandlerId) {
         match msg {
-            Send::Forward(server_message) => self.task.send(Json(&server_message)),
-            Send::SubscribeToView(view_id) => self.view_subscribers.push(ViewSubscriber {
-                handler_id: who,
-                view_id,
-            }),
-            Send::Subscribe => self.subscriber = Some(who),
+            Send::Forward(client_message) => {
+                if !self.send(&client_message) {
+                    self.console
+                        .warn(&format!("Not connected, dropping {:?}", client_message));
+                }
+            }
+            Send::SubscribeToView(view_id) => self
+                .view_subscribers
+                .entry(view_id)
+                .or_insert_with(Vec::new)
+                .push(who),
+            Send::UnsubscribeFromView(view_id) => {
+                if let Some(handlers) = self.view_subscribers.get_mut(&view_id) {
+                    handlers.retain(|h| *h != who);
+                }
+                self.view_subscribers
+                    .retain(|_, handlers| !handlers.is_empty());
+            }
+            Send::Subscribe => self.subscribers.push(who),
+            Send::Request(request) => {
+                let id = RequestId(self.next_request_id);
+                self.next_request_id += 1;
+                if !self.send(&request.clone().into_message(id)) {
+                    let error = disconnected_error();
+                    self.link
+                        .respond(who, Receive::Response(request, Err(error)));
+                    return;
+                }
+                self.pending.insert(
+                    id,
+                    Pending {
+                        handler_id: who,
+                        request,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// What a request gets when the connection to the server is lost.
+fn disconnected_error() -> RpcError {
+    RpcError {
+        code: 0,
+        message: String::from("Not connected to the server"),
+        data: None,
+    }
+}
+
+impl Mediary {
+    fn connect(&mut self) {
+        let receive = self.link.callback(|Frame(data)| match data {
+            Ok(server_message) => Callback::Receive(server_message),
+            Err(e) => Callback::Malformed(e),
+        });
+        let status = self.link.callback(Callback::Status);
+        match self.socket_service.connect(&endpoint(), receive, status) {
+            Ok(task) => self.task = Some(task),
+            Err(e) => {
+                self.console
+                    .error(&format!("Couldn't connect to the server: {}", e));
+                self.schedule_reconnect();
+            }
+        }
+    }
+
+    /// Tries to connect again after a delay that grows with each retry.
+    fn schedule_reconnect(&mut self) {
+        if self.reconnect.is_some() {
+            return;
+        }
+        // the exponent stops growing once the delay is past the cap anyway
+        let backoff = 2u32.pow(cmp::min(self.retries, 8));
+        let delay = cmp::min(RECONNECT_DELAY * backoff, MAX_RECONNECT_DELAY);
+        self.retries += 1;
+        self.console
+            .log(&format!("Reconnecting in {}ms", delay.as_millis()));
+        let callback = self.link.callback(|_| Callback::Reconnect);
+        self.reconnect = Some(self.timeout.spawn(delay, callback));
+    }
+
+    /// Fails the requests waiting on the lost connection and starts
+    /// reconnecting.
+    fn disconnected_from_server(&mut self) {
+        for (_, pending) in self.pending.drain() {
+            let response = Receive::Response(pending.request, Err(disconnected_error()));
+            self.link.respond(pending.handler_id, response);
+        }
+        self.schedule_reconnect();
+    }
+
+    /// Sends a message to the server, returning false if not connected.
+    fn send(&mut self, client_message: &ClientMessage) -> bool {
+        match &mut self.task {
+            Some(task) => {
+                match self.encoding {
+                    Encoding::Json => task.send(Json(client_message)),
+                    Encoding::Cbor => {
+                        task.send_binary(client_message.to_binary().map_err(failure::Error::from))
+                    }
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sends a copy of `server_message` to each of `handlers`.
+    fn forward(&self, handlers: Option<&Vec<HandlerId>>, server_message: ServerMessage) {
+        for handler in handlers.into_iter().flatten() {
+            self.link
+                .respond(*handler, Receive::Forward(server_message.clone()));
+        }
+    }
+
+    /// Delivers the response to `id` to whoever sent the request.
+    fn resolve<F>(&mut self, id: RequestId, decode: F)
+    where
+        F: FnOnce(&Request) -> Result<Response, RpcError>,
+    {
+        match self.pending.remove(&id) {
+            Some(Pending {
+                handler_id,
+                request,
+            }) => {
+                let response = decode(&request);
+                self.link
+                    .respond(handler_id, Receive::Response(request, response));
+            }
+            None => self
+                .console
+                .warn(&format!("Dropping response to unknown request {:?}", id)),
         }
     }
 }
@@ -0,0 +1,98 @@
+use stdweb::unstable::TryInto;
+use stdweb::{js, Value};
+use zn_core::messages::{Color, ConfigChangedParamsChanges, ThemeSettings};
+use zn_core::styles::Style;
+
+/// An xi ARGB color as a CSS color.
+pub fn css_color(argb: u32) -> String {
+    format!(
+        "rgba({}, {}, {}, {:.3})",
+        (argb >> 16) & 0xff,
+        (argb >> 8) & 0xff,
+        argb & 0xff,
+        f64::from(argb >> 24) / 255.0
+    )
+}
+
+/// The inline CSS for a run of text in `style`.
+pub fn css(style: &Style) -> String {
+    let mut css = String::new();
+    if let Some(fg) = style.fg_color {
+        css.push_str(&format!("color: {};", css_color(fg)));
+    }
+    if let Some(bg) = style.bg_color {
+        css.push_str(&format!("background-color: {};", css_color(bg)));
+    }
+    if let Some(weight) = style.weight {
+        css.push_str(&format!("font-weight: {};", weight));
+    }
+    if style.italic == Some(true) {
+        css.push_str("font-style: italic;");
+    }
+    if style.underline == Some(true) {
+        css.push_str("text-decoration: underline;");
+    }
+    css
+}
+
+/// The inline CSS for a view with `config`. xi-core does the wrapping, and
+/// sends each visual row as a line of its own.
+pub fn view_css(config: &ConfigChangedParamsChanges) -> String {
+    let mut css = String::new();
+    if let Some(font_face) = &config.font_face {
+        css.push_str(&format!("font-family: \"{}\", monospace;", font_face));
+    }
+    if let Some(font_size) = config.font_size {
+        css.push_str(&format!("font-size: {}px;", font_size));
+    }
+    if let Some(tab_size) = config.tab_size {
+        css.push_str(&format!("tab-size: {0}; -moz-tab-size: {0};", tab_size));
+    }
+    css
+}
+
+/// The widths in pixels of `strings` set in `style`, in the font of the
+/// active view, for xi-core's `measure_width`.
+pub fn measure_widths(style: &Style, strings: &[String]) -> Vec<f64> {
+    let weight = style.weight.unwrap_or(400);
+    let italic = style.italic == Some(true);
+    let widths: Value = js! {
+        var canvas = window.znMeasureCanvas ||
+            (window.znMeasureCanvas = document.createElement("canvas"));
+        var context = canvas.getContext("2d");
+        var code = document.querySelector(".view-slot.active .code") || document.body;
+        var font = window.getComputedStyle(code);
+        context.font = (@{italic} ? "italic " : "") + @{weight} + " " +
+            font.fontSize + " " + font.fontFamily;
+        return @{strings}.map(function(s) { return context.measureText(s).width; });
+    };
+    widths.try_into().unwrap_or_default()
+}
+
+/// Sets the CSS variables `style.css` takes its colors from. Colors the theme
+/// leaves out go back to the stylesheet's defaults.
+pub fn apply_theme(theme: &ThemeSettings) {
+    let variables = [
+        ("--background", theme.background),
+        ("--foreground", theme.foreground),
+        ("--caret", theme.caret),
+        ("--selection", theme.selection),
+        ("--gutter", theme.gutter),
+        ("--gutter-foreground", theme.gutter_foreground),
+        ("--line-highlight", theme.line_highlight),
+        ("--find-highlight", theme.find_highlight),
+    ];
+    for &(name, color) in variables.iter() {
+        match color {
+            Some(Color { r, g, b, a }) => {
+                let value = format!("rgba({}, {}, {}, {:.3})", r, g, b, f64::from(a) / 255.0);
+                js! { @(no_return)
+                    document.documentElement.style.setProperty(@{name}, @{value});
+                }
+            }
+            None => js! { @(no_return)
+                document.documentElement.style.removeProperty(@{name});
+            },
+        }
+    }
+}
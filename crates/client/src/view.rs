@@ -1,37 +1,225 @@
 use socket::*;
+use std::rc::Rc;
+use stdweb::{js, unstable::TryInto, Value};
 use yew::{
-    prelude::*, services::ConsoleService, virtual_dom::VNode, Component, ComponentLink, Properties,
+    prelude::*,
+    services::{
+        resize::{ResizeService, ResizeTask},
+        ConsoleService,
+    },
+    virtual_dom::VNode,
+    Component, ComponentLink, NodeRef, Properties,
 };
 use zn_core::{
-    messages,
+    line_cache::LineCache,
     messages::{
-        ClientMessage, Edit, GestureParams, GestureType, Method, ServerMessage, UpdateUpdateParams,
+        Annotation, ClientMessage, ConfigChangedParamsChanges, Edit, GestureParams, GestureType,
+        Method, ResizeParams, ScrollParams, ServerMessage, ViewId,
     },
+    styles::StyleTable,
 };
-use {bus, socket};
 
-use bus::Output;
-use line::Line;
-use view::Message::Nothing;
+use line::{Highlight, Line, Pointer};
+use style;
 
 #[derive(Clone, Properties, PartialEq)]
 pub struct Props {
     pub id: String,
+    pub style_table: Rc<StyleTable>,
+    /// Whether the view is the one showing. Hidden views stay mounted, so
+    /// they catch up with their size and scroll when shown again.
+    pub active: bool,
+    /// Called with the view's id when the user clicks into it.
+    #[props(required)]
+    pub on_focus: Callback<ViewId>,
 }
 
 pub enum Message {
     Apply(Method),
-    Click(u64, u64),
+    Presence(Vec<Annotation>),
+    Pointer(u64, u64, Pointer),
+    Scrolled,
+    Resized,
     Nothing,
 }
 
 pub struct View {
     id: String,
+    style_table: Rc<StyleTable>,
+    active: bool,
+    on_focus: Callback<ViewId>,
     link: ComponentLink<Self>,
     console: ConsoleService,
     socket: Box<dyn Bridge<socket::Mediary>>,
-    linebus: Box<dyn Bridge<bus::LineBus>>,
-    lines: Vec<messages::Line>,
+    lines: LineCache,
+    /// The annotations of the last update, which replace the earlier ones.
+    annotations: Vec<Annotation>,
+    /// The selections of other clients on the same file.
+    presence: Vec<Annotation>,
+    /// Everything xi-core has told us about the view's settings so far.
+    config: ConfigChangedParamsChanges,
+    dragging: bool,
+    /// The scrolling element, for measuring the viewport.
+    container: NodeRef,
+    line_height: f64,
+    /// The size of the text last sent to xi-core with `resize`, which it
+    /// wraps to when `wrap_width` is 0.
+    size: (u64, u64),
+    _resize: ResizeTask,
+    /// The lines that are drawn, the visible ones plus `OVERSCAN` on either
+    /// side. xi-core was told about them with `scroll`.
+    drawn: (usize, usize),
+}
+
+/// Lines drawn above and below the visible ones, so a short scroll doesn't
+/// show blanks while xi-core sends the lines.
+const OVERSCAN: usize = 20;
+
+/// The line height in pixels until a line has been drawn to measure.
+const DEFAULT_LINE_HEIGHT: f64 = 20.0;
+
+/// Where a view is scrolled to, in pixels.
+struct Viewport {
+    scroll_top: f64,
+    height: f64,
+    /// The width left for the text next to the gutter.
+    width: f64,
+    /// The height of the first line drawn, or 0 if there is none.
+    line_height: f64,
+}
+
+fn measure(container: &NodeRef) -> Option<Viewport> {
+    let node = container.get()?;
+    let sizes: Value = js! {
+        var node = @{node};
+        var line = node.querySelector(".line");
+        var gutter = node.querySelector(".line .gutter");
+        var width = node.clientWidth;
+        if (gutter) {
+            width -= gutter.offsetWidth + parseFloat(window.getComputedStyle(gutter).marginRight);
+        }
+        return [node.scrollTop, node.clientHeight, width, line ? line.offsetHeight : 0];
+    };
+    let sizes: Vec<f64> = sizes.try_into().ok()?;
+    match sizes[..] {
+        [scroll_top, height, width, line_height] => Some(Viewport {
+            scroll_top,
+            height,
+            width,
+            line_height,
+        }),
+        _ => None,
+    }
+}
+
+fn set_scroll_top(container: &NodeRef, scroll_top: f64) {
+    if let Some(node) = container.get() {
+        js! { @(no_return)
+            @{node}.scrollTop = @{scroll_top};
+        }
+    }
+}
+
+/// Picks the xi gesture for a mouse press, following the usual conventions:
+/// double-click selects words, triple-click selects lines, shift extends the
+/// selection and ctrl/cmd adds a new selection.
+fn gesture_type(click_count: i32, toggle: bool, extend: bool) -> GestureType {
+    match (click_count, toggle, extend) {
+        (_, _, true) => GestureType::RangeSelect,
+        (2, false, _) => GestureType::WordSelect,
+        (2, true, _) => GestureType::MultiWordSelect,
+        (n, false, _) if n >= 3 => GestureType::LineSelect,
+        (n, true, _) if n >= 3 => GestureType::MultiLineSelect,
+        (_, true, _) => GestureType::ToggleSel,
+        _ => GestureType::PointSelect,
+    }
+}
+
+impl View {
+    /// What the annotations mark on the line at `index`, one layer per
+    /// annotation with the class to draw it with.
+    fn highlights(&self, index: u64, len: usize) -> Vec<Highlight> {
+        self.annotations
+            .iter()
+            .chain(&self.presence)
+            .map(|annotation| {
+                let class = match annotation {
+                    Annotation::Selection { .. } => String::from("selection"),
+                    Annotation::Find { .. } => String::from("find-hit"),
+                    Annotation::Presence { peer, .. } => format!("presence peer-{}", peer % 4),
+                    Annotation::Plugin { kind, .. } => format!("annotation {}", kind),
+                };
+                let mut spans = annotation.spans_on_line(index, len);
+                // the carets of empty selections come with the line's cursors,
+                // but other clients' carets are drawn as empty spans
+                let carets = match annotation {
+                    Annotation::Presence { .. } => true,
+                    _ => false,
+                };
+                if !carets {
+                    spans.retain(|(start, end)| start < end);
+                }
+                Highlight { class, spans }
+            })
+            .filter(|highlight| !highlight.spans.is_empty())
+            .collect()
+    }
+
+    /// Works out which lines to draw from the viewport, and tells xi-core
+    /// when they or the view's size change. Returns whether the lines did.
+    fn update_viewport(&mut self) -> bool {
+        let viewport = match measure(&self.container) {
+            Some(viewport) => viewport,
+            None => return false,
+        };
+        if viewport.line_height > 0.0 {
+            self.line_height = viewport.line_height;
+        }
+        let size = (viewport.width.max(0.0) as u64, viewport.height as u64);
+        if size != self.size {
+            self.size = size;
+            self.socket.send(Send::Forward(ClientMessage::Edit {
+                id: None,
+                params: Edit::Resize {
+                    params: ResizeParams {
+                        width: size.0,
+                        height: size.1,
+                    },
+                    view_id: self.id.clone(),
+                },
+            }));
+        }
+        let first = (viewport.scroll_top / self.line_height) as usize;
+        let last = ((viewport.scroll_top + viewport.height) / self.line_height).ceil() as usize;
+        let drawn = (first.saturating_sub(OVERSCAN), last + OVERSCAN);
+        if drawn == self.drawn {
+            return false;
+        }
+        self.drawn = drawn;
+        self.socket.send(Send::Forward(ClientMessage::Edit {
+            id: None,
+            params: Edit::Scroll {
+                params: ScrollParams(drawn.0, drawn.1),
+                view_id: self.id.clone(),
+            },
+        }));
+        true
+    }
+
+    /// Scrolls just enough to bring `line` into view.
+    fn scroll_to(&mut self, line: u64) {
+        let viewport = match measure(&self.container) {
+            Some(viewport) => viewport,
+            None => return,
+        };
+        let top = line as f64 * self.line_height;
+        let bottom = top + self.line_height;
+        if top < viewport.scroll_top {
+            set_scroll_top(&self.container, top);
+        } else if bottom > viewport.scroll_top + viewport.height {
+            set_scroll_top(&self.container, bottom - viewport.height);
+        }
+    }
 }
 
 impl Component for View {
@@ -39,101 +227,97 @@ impl Component for View {
     type Properties = Props;
 
     fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
-        let callback = link.callback(|Receive::Forward(server_message)| {
-            if let ServerMessage::EditorMethod(Method::Update { update, view_id }) = server_message
-            {
-                Message::Apply(Method::Update { update, view_id })
-            } else {
-                Message::Nothing
+        let callback = link.callback(|receive| match receive {
+            Receive::Forward(ServerMessage::EditorMethod(method)) => Message::Apply(method),
+            Receive::Forward(ServerMessage::Presence { annotations, .. }) => {
+                Message::Presence(annotations)
             }
+            _ => Message::Nothing,
         });
         // `Worker::bridge` spawns an instance if no one is available
         let mut socket = socket::Mediary::bridge(callback); // Connected! :tada:
         socket.send(socket::Send::SubscribeToView(props.id.clone()));
-
-        let linebus = bus::LineBus::bridge(link.callback(|Output::Event(_, _)| Nothing));
+        let resize = ResizeService::new().register(link.callback(|_| Message::Resized));
 
         View {
             id: props.id,
+            style_table: props.style_table,
+            active: props.active,
+            on_focus: props.on_focus,
             link,
             console: ConsoleService::new(),
             socket,
-            linebus,
-            lines: Vec::new(),
+            lines: LineCache::new(),
+            annotations: Vec::new(),
+            presence: Vec::new(),
+            config: ConfigChangedParamsChanges::default(),
+            dragging: false,
+            container: NodeRef::default(),
+            line_height: DEFAULT_LINE_HEIGHT,
+            size: (0, 0),
+            _resize: resize,
+            drawn: (0, 0),
         }
     }
 
+    fn mounted(&mut self) -> ShouldRender {
+        self.update_viewport()
+    }
+
     fn update(&mut self, msg: Self::Message) -> bool {
         let should_render: bool = match msg {
             Message::Nothing => false,
-            Message::Click(line, pos) => {
-                self.socket.send(Send::Forward(ClientMessage::Edit {
-                    params: Edit::Gesture {
-                        params: GestureParams {
-                            line: line,
-                            col: pos as u64,
-                            ty: GestureType::PointSelect,
+            Message::Pointer(line, col, pointer) => {
+                let ty = match pointer {
+                    Pointer::Down {
+                        click_count,
+                        toggle,
+                        extend,
+                    } => {
+                        self.on_focus.emit(self.id.clone());
+                        self.dragging = true;
+                        Some(gesture_type(click_count, toggle, extend))
+                    }
+                    Pointer::Move if self.dragging => Some(GestureType::Drag),
+                    Pointer::Move => None,
+                    Pointer::Up => {
+                        self.dragging = false;
+                        None
+                    }
+                };
+
+                if let Some(ty) = ty {
+                    self.socket.send(Send::Forward(ClientMessage::Edit {
+                        id: None,
+                        params: Edit::Gesture {
+                            params: GestureParams { line, col, ty },
+                            view_id: self.id.clone(),
                         },
-                        view_id: "view-id-1".to_string(),
-                    },
-                }));
+                    }));
+                }
 
                 false
             }
-            Message::Apply(Method::Update {
-                update:
-                    UpdateUpdateParams {
-                        annotations,
-                        ops,
-                        pristine,
-                    },
-                view_id,
-            }) => {
-                // alrighty.. current issue is if we publish to the event bus
-                // originally, and the line hasn't even mounted yet,
-                // the line won't get the message.
-                // if   !self.lines_rendered { self.lines = such and such }
-                // else linebus.send(ops)
-
-                self.console
-                    .info(&format!("Got lines of length {}", self.lines.len()));
-
-                if self.lines.len() == 0 {
-                    self.lines = ops
-                        .into_iter()
-                        .flat_map(|op| op.lines)
-                        .flatten()
-                        .collect::<Vec<messages::Line>>();
-
-                    true
-                } else {
-                    ops.into_iter()
-                        .flat_map(|op| op.lines)
-                        .flatten()
-                        .for_each(|line| {
-                            self.linebus.send(bus::Input::Event(
-                                String::from(format!("{}", line.ln)),
-                                bus::Event::Insert {
-                                    cursor: None,
-                                    text: line.text.clone(),
-                                },
-                            ))
-                        });
-
-                    false
-                }
-
-                // so we either need to seed the line with some text
-                // or mount it before we get this far
-
-                // also how do we avoid rendering in general..
-
-                //let text = update.ops.into_iter()
-                //    .flat_map(|op| {
-                //        op.lines
-                //    }).flatten().collect::<Vec<Line>>();
-                //
-                //self.lines = text;
+            Message::Apply(Method::Update { update, .. }) => {
+                self.lines.apply(update.ops);
+                self.annotations = update.annotations;
+                // the first lines drawn give the line height
+                self.update_viewport();
+                true
+            }
+            Message::Apply(Method::ScrollTo { line, .. }) => {
+                // moving the viewport fires a scroll event, which does the rest
+                self.scroll_to(line);
+                false
+            }
+            Message::Presence(presence) => {
+                self.presence = presence;
+                true
+            }
+            Message::Scrolled | Message::Resized => self.update_viewport(),
+            Message::Apply(Method::ConfigChanged { changes, .. }) => {
+                self.config.merge(changes);
+                true
             }
             _ => false,
         };
@@ -141,22 +325,71 @@ impl Component for View {
         should_render
     }
 
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.on_focus = props.on_focus;
+        let restyled = self.style_table != props.style_table;
+        self.style_table = props.style_table;
+        let shown = props.active && !self.active;
+        self.active = props.active;
+        if self.id == props.id {
+            if shown {
+                // it may have been resized or scrolled while hidden, so tell
+                // xi-core again
+                self.size = (0, 0);
+                self.drawn = (0, 0);
+                return self.update_viewport() || restyled;
+            }
+            return restyled;
+        }
+        self.socket.send(Send::UnsubscribeFromView(self.id.clone()));
+        self.socket.send(Send::SubscribeToView(props.id.clone()));
+        self.id = props.id;
+        self.lines = LineCache::new();
+        self.annotations = Vec::new();
+        self.presence = Vec::new();
+        self.config = ConfigChangedParamsChanges::default();
+        self.size = (0, 0);
+        self.drawn = (0, 0);
+        set_scroll_top(&self.container, 0.0);
+        self.update_viewport();
+        true
+    }
+
     fn view(&self) -> VNode {
+        let mut class = String::from("view");
+        if self.config.scroll_past_end == Some(true) {
+            class.push_str(" scroll-past-end");
+        }
+        // only the drawn lines are in the DOM, the rest is padding
+        let height = self.lines.height();
+        let last = self.drawn.1.min(height);
+        let first = self.drawn.0.min(last);
+        let padding = |lines: usize| format!("height: {}px", lines as f64 * self.line_height);
+
         html! {
-            <div>
+            <div class=class
+                style=style::view_css(&self.config)
+                ref=self.container.clone()
+                onscroll=self.link.callback(|_: ScrollEvent| Message::Scrolled)>
+                <div style=padding(first)></div>
                 {
-                    for self.lines.iter().map(|line| {
-
-                        html! {
+                    for self.lines.range(first, last).zip(first..).map(|(line, index)| match line {
+                        Some(line) => html! {
                             <Line
-                                id={line.ln.clone()}
-                                on_custom_fn={self.link.callback(|(ln, pos)| Message::Click(ln, pos))}
+                                id={index as u64}
+                                ln={line.ln}
+                                on_pointer={self.link.callback(|(line, col, pointer)| Message::Pointer(line, col, pointer))}
                                 text={line.text.clone()}
+                                styles={line.styles.clone()}
+                                style_table={Rc::clone(&self.style_table)}
                                 cursor={line.cursor.clone()}
+                                highlights={self.highlights(index as u64, line.text.len())}
                             />
-                        }
+                        },
+                        None => html! { <div class="line" style=padding(1)></div> },
                     })
                 }
+                <div style=padding(height - last)></div>
             </div>
         }
     }
@@ -1,6 +1,6 @@
 import init, { run_app } from '../pkg/zn_client.js';
 async function main() {
-    await init('http://localhost:8085/pkg/zn_client_bg.wasm');
+    await init('/zn_client_bg.wasm');
     run_app();
 }
-main()
\ No newline at end of file
+main()
@@ -1,6 +1,8 @@
-extern crate bincode;
 extern crate serde;
+extern crate serde_cbor;
 extern crate serde_json;
 extern crate uuid;
 
+pub mod line_cache;
 pub mod messages;
+pub mod styles;
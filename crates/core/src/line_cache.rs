@@ -0,0 +1,221 @@
+use messages::{Line, OpType, UpdateOp};
+use std::collections::VecDeque;
+
+/// A run of consecutive lines in the cache. Invalid lines are lines the
+/// core knows about but hasn't sent yet, so they are stored as a count.
+#[derive(Clone, Debug)]
+enum Run {
+    Invalid(usize),
+    Valid(Vec<Line>),
+}
+
+impl Run {
+    fn len(&self) -> usize {
+        match self {
+            Run::Invalid(n) => *n,
+            Run::Valid(lines) => lines.len(),
+        }
+    }
+
+    /// Splits off the first `n` lines of the run, leaving the rest in `self`.
+    fn split_front(&mut self, n: usize) -> Run {
+        match self {
+            Run::Invalid(len) => {
+                *len -= n;
+                Run::Invalid(n)
+            }
+            Run::Valid(lines) => {
+                let rest = lines.split_off(n);
+                Run::Valid(std::mem::replace(lines, rest))
+            }
+        }
+    }
+}
+
+/// The client side copy of a view's lines, kept in sync with xi-core by
+/// applying the ops of each `update` as described in the frontend protocol.
+#[derive(Clone, Debug, Default)]
+pub struct LineCache {
+    runs: Vec<Run>,
+}
+
+impl LineCache {
+    pub fn new() -> Self {
+        LineCache { runs: Vec::new() }
+    }
+
+    /// The total number of lines, including invalid ones.
+    pub fn height(&self) -> usize {
+        self.runs.iter().map(Run::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    /// Returns the line at `ix`, or `None` if it is invalid or out of range.
+    pub fn get(&self, ix: usize) -> Option<&Line> {
+        let mut start = 0;
+        for run in &self.runs {
+            let len = run.len();
+            if ix < start + len {
+                return match run {
+                    Run::Invalid(_) => None,
+                    Run::Valid(lines) => lines.get(ix - start),
+                };
+            }
+            start += len;
+        }
+        None
+    }
+
+    /// Iterates over every line in the cache, yielding `None` for invalid lines.
+    pub fn iter(&self) -> impl Iterator<Item = Option<&Line>> {
+        self.runs.iter().flat_map(|run| -> Box<dyn Iterator<Item = Option<&Line>>> {
+            match run {
+                Run::Invalid(n) => Box::new((0..*n).map(|_| None)),
+                Run::Valid(lines) => Box::new(lines.iter().map(Some)),
+            }
+        })
+    }
+
+    /// Iterates over the lines from `start` up to `end`, yielding `None` for
+    /// invalid lines. Runs before `start` are skipped whole, so this is cheap
+    /// however far into the cache the range is.
+    pub fn range(&self, start: usize, end: usize) -> impl Iterator<Item = Option<&Line>> {
+        let mut run_start = 0;
+        self.runs.iter().flat_map(move |run| -> Box<dyn Iterator<Item = Option<&Line>>> {
+            let len = run.len();
+            let from = start.max(run_start).min(run_start + len) - run_start;
+            let to = end.max(run_start).min(run_start + len) - run_start;
+            run_start += len;
+            match run {
+                _ if from >= to => Box::new(std::iter::empty()),
+                Run::Invalid(_) => Box::new((from..to).map(|_| None)),
+                Run::Valid(lines) => Box::new(lines[from..to].iter().map(Some)),
+            }
+        })
+    }
+
+    /// The ops that rebuild this cache from any other, for bringing a client
+    /// that lost track back in sync. Lines are inserted, never copied.
+    pub fn to_ops(&self) -> Vec<UpdateOp> {
+        self.runs
+            .iter()
+            .map(|run| match run {
+                Run::Invalid(n) => UpdateOp {
+                    op: OpType::Invalidate,
+                    n: *n,
+                    lines: None,
+                    first_line_number: None,
+                },
+                Run::Valid(lines) => UpdateOp {
+                    op: OpType::Insert,
+                    n: lines.len(),
+                    lines: Some(lines.clone()),
+                    first_line_number: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Builds the new cache from the old one by applying `ops` in order.
+    ///
+    /// Lines that `copy` or `skip` would read past the end of the old cache
+    /// are treated as invalid.
+    pub fn apply(&mut self, ops: Vec<UpdateOp>) {
+        let mut old: VecDeque<Run> = self.runs.drain(..).collect();
+        let mut new = LineCache::new();
+
+        for op in ops {
+            match op.op {
+                OpType::Copy => {
+                    let mut runs = take(&mut old, op.n);
+                    if let Some(ln) = op.first_line_number {
+                        renumber(&mut runs, ln as u64);
+                    }
+                    runs.into_iter().for_each(|run| new.push(run));
+                }
+                OpType::Skip => {
+                    take(&mut old, op.n);
+                }
+                OpType::Invalidate => new.push(Run::Invalid(op.n)),
+                OpType::Insert => new.push(Run::Valid(op.lines.unwrap_or_default())),
+            }
+        }
+
+        *self = new;
+    }
+
+    /// Appends a run, merging it into the last run when both are the same kind.
+    fn push(&mut self, run: Run) {
+        if run.len() == 0 {
+            return;
+        }
+        match (self.runs.last_mut(), run) {
+            (Some(Run::Invalid(n)), Run::Invalid(m)) => *n += m,
+            (Some(Run::Valid(lines)), Run::Valid(mut more)) => lines.append(&mut more),
+            (_, run) => self.runs.push(run),
+        }
+    }
+}
+
+/// Removes the first `n` lines from `old`, padding with invalid lines if
+/// `old` runs out.
+fn take(old: &mut VecDeque<Run>, mut n: usize) -> Vec<Run> {
+    let mut taken = Vec::new();
+    while n > 0 {
+        match old.front_mut() {
+            Some(run) if run.len() <= n => {
+                n -= run.len();
+                taken.extend(old.pop_front());
+            }
+            Some(run) => {
+                taken.push(run.split_front(n));
+                n = 0;
+            }
+            None => {
+                taken.push(Run::Invalid(n));
+                n = 0;
+            }
+        }
+    }
+    taken
+}
+
+/// Gives copied lines their new logical line numbers, where `ln` is the
+/// number of the logical line the first copied line belongs to. Wrapped
+/// continuation lines have no number, and since a copied range is unchanged
+/// every numbered line moves by the same amount.
+///
+/// The shift is found from the first numbered line; if an invalid line comes
+/// before it there's no way to know, and the old numbers are kept.
+fn renumber(runs: &mut [Run], ln: u64) {
+    let mut delta = None;
+    let mut first = true;
+    'find: for run in runs.iter() {
+        match run {
+            Run::Invalid(_) => break,
+            Run::Valid(lines) => {
+                for line in lines {
+                    if let Some(old) = line.ln {
+                        let new = if first { ln } else { ln + 1 };
+                        delta = Some(new as i64 - old as i64);
+                        break 'find;
+                    }
+                    first = false;
+                }
+            }
+        }
+    }
+
+    if let Some(delta) = delta {
+        for run in runs {
+            if let Run::Valid(lines) = run {
+                for line in lines {
+                    line.ln = line.ln.map(|old| (old as i64 + delta) as u64);
+                }
+            }
+        }
+    }
+}
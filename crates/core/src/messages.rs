@@ -5,6 +5,10 @@ use uuid::Uuid;
 
 pub type ViewId = String;
 
+/// Identifies a request to xi-core, so its response can be matched back to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RequestId(pub u64);
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClientStartedParams {
     #[serde(default)]
@@ -12,20 +16,118 @@ pub struct ClientStartedParams {
     /// Path to additional plugins, included by the client.
     #[serde(default)]
     pub client_extras_dir: Option<PathBuf>,
+    /// How the client would like the server's frames encoded. The server
+    /// takes this out before passing the rest on to xi-core.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ScrollParams(Vec<usize>);
+/// How messages between the client and the server are framed. Either side
+/// reads both, `Cbor` in binary frames and `Json` in text ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Cbor,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The first visible line and the one after the last, sent as a pair.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScrollParams(pub usize, pub usize);
+
+/// Reattaches a new connection to the session it lost, in place of
+/// `client_started`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResumeParams {
+    pub session_id: Uuid,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NewViewParams {
     pub file_path: Option<String>,
 }
 
+/// Lists a directory on the server. `path` is taken from the workspace the
+/// server was started in, so `""` lists the workspace itself, and may not
+/// lead out of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ListDirectoryParams {
+    pub path: String,
+    /// Also list everything below, skipping hidden entries.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatParams {
+    pub path: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileStat {
+    /// Relative to the listed directory in a listing, absolute from `stat`.
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    /// Seconds since the Unix epoch, where the platform has it.
+    #[serde(default)]
+    pub modified: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryListing {
+    /// The absolute path of the listed directory.
+    pub path: String,
+    pub entries: Vec<FileStat>,
+    /// The listing stopped early because the directory is too large.
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SaveParams {
+    pub view_id: ViewId,
+    pub file_path: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CloseViewParams {
+    pub view_id: ViewId,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SetThemeParams {
+    pub theme_name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetConfigParams {
+    pub view_id: ViewId,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum GestureType {
     PointSelect,
+    ToggleSel,
+    RangeSelect,
+    LineSelect,
+    WordSelect,
+    MultiLineSelect,
+    MultiWordSelect,
+    Drag,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -36,13 +138,347 @@ pub struct GestureParams {
     pub ty: GestureType,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CharsParams {
+    pub chars: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GotoLineParams {
+    pub line: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResizeParams {
+    pub width: u64,
+    pub height: u64,
+}
+
+/// The first and last line (exclusive) of a range, sent as a two element array.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineRange(pub usize, pub usize);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FindParams {
+    pub chars: String,
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub whole_words: bool,
+}
+
+/// What happens to the selections when moving to a match.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionModifier {
+    None,
+    Set,
+    Add,
+    AddRemovingCurrent,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FindNextParams {
+    /// Continue from the other end of the buffer after the last match.
+    pub wrap_around: bool,
+    /// Whether the match at the cursor counts as the next one.
+    pub allow_same: bool,
+    pub modify_selection: SelectionModifier,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighlightFindParams {
+    pub visible: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SelectionForFindParams {
+    pub case_sensitive: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceParams {
+    pub chars: String,
+    #[serde(default)]
+    pub preserve_case: bool,
+}
+
+/// The `edit` methods understood by xi-core. Commands without arguments
+/// are sent without `params`, which xi-core accepts for unit commands.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "method")]
 #[serde(rename_all = "snake_case")]
 pub enum Edit {
+    // Inserting
+    Insert {
+        params: CharsParams,
+        view_id: ViewId,
+    },
+    Paste {
+        params: CharsParams,
+        view_id: ViewId,
+    },
+    InsertNewline {
+        view_id: ViewId,
+    },
+    InsertTab {
+        view_id: ViewId,
+    },
+
+    // Deleting
+    DeleteBackward {
+        view_id: ViewId,
+    },
+    DeleteForward {
+        view_id: ViewId,
+    },
+    DeleteWordBackward {
+        view_id: ViewId,
+    },
+    DeleteWordForward {
+        view_id: ViewId,
+    },
+    DeleteToEndOfParagraph {
+        view_id: ViewId,
+    },
+    DeleteToBeginningOfLine {
+        view_id: ViewId,
+    },
+
+    // Cursor movement
     Gesture {
         params: GestureParams,
-        view_id: String,
+        view_id: ViewId,
+    },
+    GotoLine {
+        params: GotoLineParams,
+        view_id: ViewId,
+    },
+    MoveUp {
+        view_id: ViewId,
+    },
+    MoveUpAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveDown {
+        view_id: ViewId,
+    },
+    MoveDownAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveLeft {
+        view_id: ViewId,
+    },
+    MoveLeftAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveRight {
+        view_id: ViewId,
+    },
+    MoveRightAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveWordLeft {
+        view_id: ViewId,
+    },
+    MoveWordLeftAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveWordRight {
+        view_id: ViewId,
+    },
+    MoveWordRightAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToBeginningOfParagraph {
+        view_id: ViewId,
+    },
+    MoveToBeginningOfParagraphAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToEndOfParagraph {
+        view_id: ViewId,
+    },
+    MoveToEndOfParagraphAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToLeftEndOfLine {
+        view_id: ViewId,
+    },
+    MoveToLeftEndOfLineAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToRightEndOfLine {
+        view_id: ViewId,
+    },
+    MoveToRightEndOfLineAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToBeginningOfDocument {
+        view_id: ViewId,
+    },
+    MoveToBeginningOfDocumentAndModifySelection {
+        view_id: ViewId,
+    },
+    MoveToEndOfDocument {
+        view_id: ViewId,
+    },
+    MoveToEndOfDocumentAndModifySelection {
+        view_id: ViewId,
+    },
+    /// Tells xi-core which lines are visible, so it sends those.
+    Scroll {
+        params: ScrollParams,
+        view_id: ViewId,
+    },
+    ScrollPageUp {
+        view_id: ViewId,
+    },
+    #[serde(rename = "page_up_and_modify_selection")]
+    ScrollPageUpAndModifySelection {
+        view_id: ViewId,
+    },
+    ScrollPageDown {
+        view_id: ViewId,
+    },
+    #[serde(rename = "page_down_and_modify_selection")]
+    ScrollPageDownAndModifySelection {
+        view_id: ViewId,
+    },
+
+    // Selection
+    SelectAll {
+        view_id: ViewId,
+    },
+    AddSelectionAbove {
+        view_id: ViewId,
+    },
+    AddSelectionBelow {
+        view_id: ViewId,
+    },
+    CollapseSelections {
+        view_id: ViewId,
+    },
+    SelectionIntoLines {
+        view_id: ViewId,
+    },
+    CancelOperation {
+        view_id: ViewId,
+    },
+
+    // Editing
+    Undo {
+        view_id: ViewId,
+    },
+    Redo {
+        view_id: ViewId,
+    },
+    Yank {
+        view_id: ViewId,
+    },
+    /// A request, answered with the selected text, or null without a
+    /// selection.
+    Copy {
+        view_id: ViewId,
+    },
+    /// A request like `Copy`, which also deletes the text.
+    Cut {
+        view_id: ViewId,
+    },
+    Transpose {
+        view_id: ViewId,
+    },
+    Uppercase {
+        view_id: ViewId,
+    },
+    Lowercase {
+        view_id: ViewId,
+    },
+    Capitalize {
+        view_id: ViewId,
+    },
+    Indent {
+        view_id: ViewId,
+    },
+    Outdent {
+        view_id: ViewId,
+    },
+    Reindent {
+        view_id: ViewId,
+    },
+    DuplicateLine {
+        view_id: ViewId,
+    },
+    IncreaseNumber {
+        view_id: ViewId,
+    },
+    DecreaseNumber {
+        view_id: ViewId,
+    },
+
+    // Find and replace
+    Find {
+        params: FindParams,
+        view_id: ViewId,
+    },
+    FindNext {
+        params: FindNextParams,
+        view_id: ViewId,
+    },
+    FindPrevious {
+        params: FindNextParams,
+        view_id: ViewId,
+    },
+    FindAll {
+        view_id: ViewId,
+    },
+    HighlightFind {
+        params: HighlightFindParams,
+        view_id: ViewId,
+    },
+    SelectionForFind {
+        params: SelectionForFindParams,
+        view_id: ViewId,
+    },
+    /// Sets what `replace_next` and `replace_all` replace matches with.
+    Replace {
+        params: ReplaceParams,
+        view_id: ViewId,
+    },
+    ReplaceNext {
+        view_id: ViewId,
+    },
+    ReplaceAll {
+        view_id: ViewId,
+    },
+    SelectionForReplace {
+        view_id: ViewId,
+    },
+
+    // View
+    Resize {
+        params: ResizeParams,
+        view_id: ViewId,
+    },
+    RequestLines {
+        params: LineRange,
+        view_id: ViewId,
+    },
+
+    // Recordings, which xi-core names with a bare string as the params
+    /// Starts or stops recording, under the default name if there's none.
+    ToggleRecording {
+        params: Option<String>,
+        view_id: ViewId,
+    },
+    PlayRecording {
+        params: String,
+        view_id: ViewId,
+    },
+    ClearRecording {
+        params: String,
+        view_id: ViewId,
     },
 }
 // always { method: "", params: "", .. sometimes extra, like id: "" }
@@ -54,17 +490,41 @@ pub enum Edit {
 pub enum ClientMessage {
     Ping { v: i64 },
 
-    // CloseView { view_id: ViewId },
-    // Save { view_id: ViewId, file_path: String },
     ClientStarted { params: ClientStartedParams },
 
-    NewView { id: usize, params: NewViewParams },
+    Resume { params: ResumeParams },
+
+    /// Writes the view's buffer to `file_path`, which also serves as save-as.
+    Save { params: SaveParams },
+
+    CloseView { params: CloseViewParams },
+
+    NewView { id: RequestId, params: NewViewParams },
+
+    GetConfig { id: RequestId, params: GetConfigParams },
+
+    /// Answered by the zn server itself rather than xi-core.
+    ListDirectory { id: RequestId, params: ListDirectoryParams },
 
-    Scroll { params: ScrollParams },
+    /// Answered by the zn server itself rather than xi-core.
+    Stat { id: RequestId, params: StatParams },
+
+    /// Switches every view to one of the themes from `available_themes`.
+    SetTheme { params: SetThemeParams },
 
     GetVersion,
 
-    Edit { params: Edit },
+    /// An edit, sent with an `id` for the ones xi-core answers, `copy` and
+    /// `cut`.
+    Edit {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<RequestId>,
+        params: Edit,
+    },
+
+    /// Answers xi-core's `measure_width`, which the server passes on as a
+    /// response to `id`.
+    MeasuredWidths { id: RequestId, widths: Vec<Vec<f64>> },
 }
 
 impl ClientMessage {
@@ -73,49 +533,236 @@ impl ClientMessage {
     }
 
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self)
+    }
+
+    /// For logging, the wire wants `to_json`.
+    pub fn to_json_pretty(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(&self)
     }
 
-    pub fn from_binary(b: &[u8]) -> Result<Self, bincode::Error> {
-        bincode::deserialize(b)
+    pub fn from_binary(b: &[u8]) -> Result<Self, serde_cbor::Error> {
+        serde_cbor::from_slice(b)
     }
 
-    pub fn to_binary(&self) -> Result<Vec<u8>, bincode::Error> {
-        bincode::serialize(&self)
+    pub fn to_binary(&self) -> Result<Vec<u8>, serde_cbor::Error> {
+        serde_cbor::to_vec(&self)
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A view's settings. xi-core sends all of them when a view opens, and
+/// after that only the ones that changed, so every field is optional.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct ConfigChangedParamsChanges {
-    auto_indent: bool,
-    autodetect_whitespace: bool,
-    font_face: String,
-    font_size: u64,
-    line_ending: String,
-    plugin_search_path: Vec<String>,
-    save_with_newline: bool,
-    scroll_past_end: bool,
-    surrounding_pairs: Vec<Vec<String>>,
-    tab_size: u64,
-    translate_tabs_to_spaces: bool,
-    use_tab_stops: bool,
-    word_wrap: bool,
-    wrap_width: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_indent: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autodetect_whitespace: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_face: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin_search_path: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save_with_newline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll_past_end: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surrounding_pairs: Option<Vec<Vec<String>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tab_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translate_tabs_to_spaces: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_tab_stops: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word_wrap: Option<bool>,
+    /// Column to wrap at when `word_wrap` is on, 0 for the width of the view.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrap_width: Option<u64>,
+}
+
+impl ConfigChangedParamsChanges {
+    /// Takes on every setting `changes` has.
+    pub fn merge(&mut self, changes: ConfigChangedParamsChanges) {
+        self.auto_indent = changes.auto_indent.or(self.auto_indent.take());
+        self.autodetect_whitespace = changes
+            .autodetect_whitespace
+            .or(self.autodetect_whitespace.take());
+        self.font_face = changes.font_face.or(self.font_face.take());
+        self.font_size = changes.font_size.or(self.font_size.take());
+        self.line_ending = changes.line_ending.or(self.line_ending.take());
+        self.plugin_search_path = changes
+            .plugin_search_path
+            .or(self.plugin_search_path.take());
+        self.save_with_newline = changes.save_with_newline.or(self.save_with_newline.take());
+        self.scroll_past_end = changes.scroll_past_end.or(self.scroll_past_end.take());
+        self.surrounding_pairs = changes.surrounding_pairs.or(self.surrounding_pairs.take());
+        self.tab_size = changes.tab_size.or(self.tab_size.take());
+        self.translate_tabs_to_spaces = changes
+            .translate_tabs_to_spaces
+            .or(self.translate_tabs_to_spaces.take());
+        self.use_tab_stops = changes.use_tab_stops.or(self.use_tab_stops.take());
+        self.word_wrap = changes.word_wrap.or(self.word_wrap.take());
+        self.wrap_width = changes.wrap_width.or(self.wrap_width.take());
+    }
 }
 
+/// A set of ranges xi-core marks in a view: the selections, the matches of
+/// a search, or whatever a plugin wants to point out.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-#[serde(tag = "type")]
+#[serde(from = "AnnotationParams", into = "AnnotationParams")]
 pub enum Annotation {
-    Selection { n: u64, ranges: Vec<Vec<u64>> },
+    Selection {
+        n: u64,
+        ranges: Vec<Vec<u64>>,
+    },
+    /// The matches of the find queries, with the id of the query for each.
+    Find {
+        n: u64,
+        ranges: Vec<Vec<u64>>,
+        payloads: Option<Vec<Value>>,
+    },
+    /// Where another client editing the same file has its selections, when
+    /// the server is shared. `peer` tells the clients apart, and goes over
+    /// the wire as the payload of each range.
+    Presence {
+        peer: u64,
+        n: u64,
+        ranges: Vec<Vec<u64>>,
+    },
+    /// An annotation type defined by a plugin.
+    Plugin {
+        kind: String,
+        n: u64,
+        ranges: Vec<Vec<u64>>,
+        payloads: Option<Vec<Value>>,
+    },
 }
 
+/// How annotations go over the wire. Plugins can add types, so the type is
+/// kept as a string rather than used as a tag.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnnotationParams {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub n: u64,
+    pub ranges: Vec<Vec<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payloads: Option<Vec<Value>>,
+}
+
+impl From<AnnotationParams> for Annotation {
+    fn from(params: AnnotationParams) -> Self {
+        let AnnotationParams {
+            kind,
+            n,
+            ranges,
+            payloads,
+        } = params;
+        match kind.as_str() {
+            "selection" => Annotation::Selection { n, ranges },
+            "find" => Annotation::Find {
+                n,
+                ranges,
+                payloads,
+            },
+            "presence" => Annotation::Presence {
+                peer: payloads
+                    .as_ref()
+                    .and_then(|payloads| payloads.first())
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0),
+                n,
+                ranges,
+            },
+            _ => Annotation::Plugin {
+                kind,
+                n,
+                ranges,
+                payloads,
+            },
+        }
+    }
+}
+
+impl From<Annotation> for AnnotationParams {
+    fn from(annotation: Annotation) -> Self {
+        let (kind, n, ranges, payloads) = match annotation {
+            Annotation::Selection { n, ranges } => (String::from("selection"), n, ranges, None),
+            Annotation::Find {
+                n,
+                ranges,
+                payloads,
+            } => (String::from("find"), n, ranges, payloads),
+            Annotation::Presence { peer, n, ranges } => {
+                let payloads = ranges.iter().map(|_| Value::from(peer)).collect();
+                (String::from("presence"), n, ranges, Some(payloads))
+            }
+            Annotation::Plugin {
+                kind,
+                n,
+                ranges,
+                payloads,
+            } => (kind, n, ranges, payloads),
+        };
+        AnnotationParams {
+            kind,
+            n,
+            ranges,
+            payloads,
+        }
+    }
+}
+
+impl Annotation {
+    /// The annotated ranges, each as start line, start column, end line and
+    /// end column.
+    pub fn ranges(&self) -> &[Vec<u64>] {
+        match self {
+            Annotation::Selection { ranges, .. }
+            | Annotation::Find { ranges, .. }
+            | Annotation::Presence { ranges, .. }
+            | Annotation::Plugin { ranges, .. } => ranges,
+        }
+    }
+
+    /// The parts of `line` the annotation covers, as byte ranges into its
+    /// text. Ranges that go on to the next line are cut off at `len`.
+    pub fn spans_on_line(&self, line: u64, len: usize) -> Vec<(usize, usize)> {
+        self.ranges()
+            .iter()
+            .filter(|range| range.len() == 4 && range[0] <= line && line <= range[2])
+            .map(|range| {
+                let start = if range[0] == line {
+                    range[1] as usize
+                } else {
+                    0
+                };
+                let end = if range[2] == line {
+                    range[3] as usize
+                } else {
+                    len
+                };
+                (start.min(len), end.min(len))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Line {
     pub cursor: Option<Vec<u64>>,
-    pub ln: u64,
-    pub styles: Vec<u64>,
+    /// The logical line number, absent on the continuation of a wrapped line.
+    #[serde(default)]
+    pub ln: Option<u64>,
+    /// Style spans as (start, length, style id) triplets, see `styles::spans`.
+    #[serde(default)]
+    pub styles: Vec<i64>,
     pub text: String,
 }
 
@@ -147,6 +794,108 @@ pub struct UpdateUpdateParams {
     pub pristine: bool,
 }
 
+/// A plugin xi-core knows about for a view.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub running: bool,
+}
+
+/// A style that spans in `Update` lines refer to by id. Colors are ARGB.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DefStyleParams {
+    pub id: u64,
+    #[serde(default)]
+    pub fg_color: Option<u32>,
+    #[serde(default)]
+    pub bg_color: Option<u32>,
+    #[serde(default)]
+    pub weight: Option<u16>,
+    #[serde(default)]
+    pub italic: Option<bool>,
+    #[serde(default)]
+    pub underline: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// The editor-wide colors of a theme. Syntax colors come separately, as styles.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ThemeSettings {
+    #[serde(default)]
+    pub foreground: Option<Color>,
+    #[serde(default)]
+    pub background: Option<Color>,
+    #[serde(default)]
+    pub caret: Option<Color>,
+    #[serde(default)]
+    pub line_highlight: Option<Color>,
+    #[serde(default)]
+    pub selection: Option<Color>,
+    #[serde(default)]
+    pub selection_foreground: Option<Color>,
+    #[serde(default)]
+    pub inactive_selection: Option<Color>,
+    #[serde(default)]
+    pub gutter: Option<Color>,
+    #[serde(default)]
+    pub gutter_foreground: Option<Color>,
+    #[serde(default)]
+    pub find_highlight: Option<Color>,
+    #[serde(default)]
+    pub find_highlight_foreground: Option<Color>,
+    #[serde(default)]
+    pub highlight: Option<Color>,
+    #[serde(default)]
+    pub accent: Option<Color>,
+    #[serde(default)]
+    pub guide: Option<Color>,
+    #[serde(default)]
+    pub active_guide: Option<Color>,
+    #[serde(default)]
+    pub shadow: Option<Color>,
+}
+
+/// One search in a view and what it currently matches.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FindQueryStatus {
+    pub id: u64,
+    #[serde(default)]
+    pub chars: Option<String>,
+    #[serde(default)]
+    pub case_sensitive: Option<bool>,
+    #[serde(default)]
+    pub is_regex: Option<bool>,
+    #[serde(default)]
+    pub whole_words: Option<bool>,
+    pub matches: u64,
+    /// The lines with matches on them.
+    #[serde(default)]
+    pub lines: Vec<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceStatus {
+    pub chars: String,
+    #[serde(default)]
+    pub preserve_case: Option<bool>,
+    #[serde(default)]
+    pub is_regex: Option<bool>,
+}
+
+/// Strings xi-core wants the width of, when set in the style `id`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WidthRequest {
+    pub id: u64,
+    pub strings: Vec<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "method", content = "params")]
@@ -159,7 +908,7 @@ pub enum Method {
     },
 
     AvailablePlugins {
-        plugins: Vec<String>,
+        plugins: Vec<PluginInfo>,
         view_id: String,
     },
     ConfigChanged {
@@ -179,13 +928,219 @@ pub enum Method {
         line: u64,
         view_id: String,
     },
+
+    // Styles and themes
+    DefStyle(DefStyleParams),
+    ThemeChanged {
+        name: String,
+        theme: ThemeSettings,
+    },
+
+    // Find and replace
+    FindStatus {
+        queries: Vec<FindQueryStatus>,
+        view_id: String,
+    },
+    ReplaceStatus {
+        status: ReplaceStatus,
+        view_id: String,
+    },
+
+    // Plugins
+    PluginStarted {
+        plugin: String,
+        view_id: String,
+    },
+    PluginStopped {
+        code: i32,
+        plugin: String,
+        view_id: String,
+    },
+    /// Commands a plugin offers, in xi's command description format.
+    UpdateCmds {
+        cmds: Vec<Value>,
+        plugin: String,
+        view_id: String,
+    },
+    ShowHover {
+        request_id: u64,
+        result: String,
+        view_id: String,
+    },
+
+    // Status bar
+    AddStatusItem {
+        alignment: String,
+        key: String,
+        source: String,
+        value: String,
+        view_id: String,
+    },
+    UpdateStatusItem {
+        key: String,
+        value: String,
+        view_id: String,
+    },
+    RemoveStatusItem {
+        key: String,
+        view_id: String,
+    },
+
+    Alert {
+        msg: String,
+    },
+
+    /// A request xi-core blocks on until it's answered with the widths of
+    /// the strings, in pixels, one list per `WidthRequest`. xi-core sends
+    /// it with its own id and the requests as the bare params.
+    MeasureWidth {
+        id: RequestId,
+        requests: Vec<WidthRequest>,
+    },
+
+    /// A method zn has no variant for, or whose params it couldn't make
+    /// sense of, e.g. from a newer xi-core.
+    Unknown {
+        method: String,
+        params: Value,
+    },
+}
+
+impl Method {
+    /// The view this method is about, or `None` for global notifications.
+    pub fn view_id(&self) -> Option<&ViewId> {
+        match self {
+            Method::AvailableLanguages { .. }
+            | Method::AvailableThemes { .. }
+            | Method::DefStyle(_)
+            | Method::ThemeChanged { .. }
+            | Method::Alert { .. }
+            | Method::MeasureWidth { .. } => None,
+            Method::AvailablePlugins { view_id, .. }
+            | Method::ConfigChanged { view_id, .. }
+            | Method::LanguageChanged { view_id, .. }
+            | Method::Update { view_id, .. }
+            | Method::ScrollTo { view_id, .. }
+            | Method::FindStatus { view_id, .. }
+            | Method::ReplaceStatus { view_id, .. }
+            | Method::PluginStarted { view_id, .. }
+            | Method::PluginStopped { view_id, .. }
+            | Method::UpdateCmds { view_id, .. }
+            | Method::ShowHover { view_id, .. }
+            | Method::AddStatusItem { view_id, .. }
+            | Method::UpdateStatusItem { view_id, .. }
+            | Method::RemoveStatusItem { view_id, .. } => Some(view_id),
+            Method::Unknown { params, .. } => match params.get("view_id") {
+                Some(Value::String(view_id)) => Some(view_id),
+                _ => None,
+            },
+        }
+    }
+
+    /// Reads a method from xi-core, falling back to `Unknown` rather than
+    /// failing. Only `None` if `v` isn't shaped like a method at all.
+    pub fn from_xi_value(v: &Value) -> Option<Method> {
+        if v.get("method").and_then(Value::as_str) == Some("measure_width") {
+            if let (Some(id), Some(params)) = (v.get("id"), v.get("params")) {
+                let id = RequestId::deserialize(id);
+                let requests = Vec::<WidthRequest>::deserialize(params);
+                if let (Ok(id), Ok(requests)) = (id, requests) {
+                    return Some(Method::MeasureWidth { id, requests });
+                }
+            }
+        }
+        if let Ok(method) = Method::deserialize(v) {
+            return Some(method);
+        }
+        let method = v.get("method")?.as_str()?;
+        Some(Method::Unknown {
+            method: String::from(method),
+            params: v.get("params").cloned().unwrap_or(Value::Null),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default)]
+    pub data: Option<Value>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(untagged)]
 pub enum Notification {
-    Result { id: u64, result: String },
+    Result { id: RequestId, result: Value },
+    Error { id: RequestId, error: RpcError },
+}
+
+/// A call to xi-core that expects a response. The id is filled in when the
+/// request is sent, and the request is kept so the result can be decoded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Request {
+    NewView(NewViewParams),
+    GetConfig(GetConfigParams),
+    ListDirectory(ListDirectoryParams),
+    Stat(StatParams),
+    Copy(ViewId),
+    Cut(ViewId),
+}
+
+/// The decoded result of a `Request`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response {
+    NewView(ViewId),
+    GetConfig(ConfigChangedParamsChanges),
+    ListDirectory(DirectoryListing),
+    Stat(FileStat),
+    /// The text copied, if there was a selection.
+    Copy(Option<String>),
+    Cut(Option<String>),
+}
+
+impl Request {
+    pub fn into_message(self, id: RequestId) -> ClientMessage {
+        match self {
+            Request::NewView(params) => ClientMessage::NewView { id, params },
+            Request::GetConfig(params) => ClientMessage::GetConfig { id, params },
+            Request::ListDirectory(params) => ClientMessage::ListDirectory { id, params },
+            Request::Stat(params) => ClientMessage::Stat { id, params },
+            Request::Copy(view_id) => ClientMessage::Edit {
+                id: Some(id),
+                params: Edit::Copy { view_id },
+            },
+            Request::Cut(view_id) => ClientMessage::Edit {
+                id: Some(id),
+                params: Edit::Cut { view_id },
+            },
+        }
+    }
+
+    pub fn decode(&self, result: Value) -> Result<Response, serde_json::Error> {
+        match self {
+            Request::NewView(_) => serde_json::from_value(result).map(Response::NewView),
+            Request::GetConfig(_) => serde_json::from_value(result).map(Response::GetConfig),
+            Request::ListDirectory(_) => {
+                serde_json::from_value(result).map(Response::ListDirectory)
+            }
+            Request::Stat(_) => serde_json::from_value(result).map(Response::Stat),
+            Request::Copy(_) => serde_json::from_value(result).map(Response::Copy),
+            Request::Cut(_) => serde_json::from_value(result).map(Response::Cut),
+        }
+    }
+}
+
+/// A keybinding read from the user's keybinding file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keybinding {
+    /// Space separated chords, e.g. `ctrl+x ctrl+s` or `C-x C-s`.
+    pub keys: String,
+    pub command: String,
+    /// Only apply the binding in this context, e.g. `find_bar_focused`.
+    #[serde(default)]
+    pub when: Option<String>,
 }
 
 /// Sent from server to client, this shared model is used for all client communication
@@ -194,10 +1149,15 @@ pub enum Notification {
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "method", content = "params")]
 pub enum ServerMessage {
+    /// The first message on a connection. A client that reconnects sends
+    /// `session_id` in `resume`, and if the session is still there it is
+    /// `resumed`: its views are kept and their lines are sent again.
     Connected {
-        connection_id: Uuid,
-        user_id: Uuid,
-        b: bool,
+        session_id: Uuid,
+        resumed: bool,
+        /// What the server encodes everything after this with.
+        #[serde(default)]
+        encoding: Encoding,
     },
     ServerError {
         reason: String,
@@ -206,8 +1166,24 @@ pub enum ServerMessage {
     Pong {
         v: i64,
     },
+    Keybindings {
+        bindings: Vec<Keybinding>,
+    },
+    /// Files given on the server's command line, for the client to open.
+    OpenFiles {
+        paths: Vec<String>,
+    },
+    /// The selections of the other clients with the view's file open, when
+    /// the server is shared. Replaces the ones sent before.
+    Presence {
+        view_id: ViewId,
+        annotations: Vec<Annotation>,
+    },
     EditorMethod(Method),
     EditorNotification(Notification),
+    /// A message from xi-core that is neither a method nor a response zn
+    /// understands, passed along as is.
+    Unrecognized(Value),
 }
 
 //impl<'de> Deserialize<'de> for ServerMessage {
@@ -219,13 +1195,19 @@ pub enum ServerMessage {
 //}
 
 impl ServerMessage {
+    /// Parses a message from xi-core. Only fails if it isn't JSON at all:
+    /// methods zn doesn't know become `Method::Unknown`, and anything else it
+    /// can't make sense of is `Unrecognized`.
     pub fn from_xi_json(s: &str) -> Result<Self, serde_json::Error> {
-        let v: Value = serde_json::from_str(s).unwrap();
-        match v.get("result") {
-            Some(_) => serde_json::from_value(v)
-                .map(|n: Notification| ServerMessage::EditorNotification(n)),
-            None => serde_json::from_value(v).map(|m: Method| ServerMessage::EditorMethod(m)),
-        }
+        let v: Value = serde_json::from_str(s)?;
+        let parsed = if v.get("result").is_some() || v.get("error").is_some() {
+            Notification::deserialize(&v)
+                .map(ServerMessage::EditorNotification)
+                .ok()
+        } else {
+            Method::from_xi_value(&v).map(ServerMessage::EditorMethod)
+        };
+        Ok(parsed.unwrap_or(ServerMessage::Unrecognized(v)))
     }
 
     pub fn from_json(s: &str) -> Result<Self, serde_json::Error> {
@@ -233,14 +1215,19 @@ impl ServerMessage {
     }
 
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self)
+    }
+
+    /// For logging, the wire wants `to_json`.
+    pub fn to_json_pretty(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(&self)
     }
 
-    pub fn from_binary(b: &[u8]) -> Result<Self, bincode::Error> {
-        bincode::deserialize(b)
+    pub fn from_binary(b: &[u8]) -> Result<Self, serde_cbor::Error> {
+        serde_cbor::from_slice(b)
     }
 
-    pub fn to_binary(&self) -> Result<Vec<u8>, bincode::Error> {
-        bincode::serialize(&self)
+    pub fn to_binary(&self) -> Result<Vec<u8>, serde_cbor::Error> {
+        serde_cbor::to_vec(&self)
     }
 }
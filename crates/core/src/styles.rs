@@ -0,0 +1,128 @@
+use messages::DefStyleParams;
+use std::collections::HashMap;
+
+/// How a run of text looks. Anything left `None` shows whatever is
+/// underneath, down to the theme's defaults. Colors are ARGB.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg_color: Option<u32>,
+    pub bg_color: Option<u32>,
+    pub weight: Option<u16>,
+    pub italic: Option<bool>,
+    pub underline: Option<bool>,
+}
+
+impl Style {
+    /// Lays `other` on top of this style.
+    pub fn merge(&mut self, other: &Style) {
+        self.fg_color = other.fg_color.or(self.fg_color);
+        self.bg_color = other.bg_color.or(self.bg_color);
+        self.weight = other.weight.or(self.weight);
+        self.italic = other.italic.or(self.italic);
+        self.underline = other.underline.or(self.underline);
+    }
+}
+
+impl From<DefStyleParams> for Style {
+    fn from(params: DefStyleParams) -> Self {
+        Style {
+            fg_color: params.fg_color,
+            bg_color: params.bg_color,
+            weight: params.weight,
+            italic: params.italic,
+            underline: params.underline,
+        }
+    }
+}
+
+/// A styled range of a line, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StyleSpan {
+    pub start: usize,
+    pub end: usize,
+    pub style_id: u64,
+}
+
+/// Decodes the `styles` of a line. xi sends them as triplets of start,
+/// length and style id, where each start is relative to the end of the span
+/// before it, so it goes negative when spans overlap.
+pub fn spans(styles: &[i64]) -> Vec<StyleSpan> {
+    let mut end = 0;
+    styles
+        .chunks_exact(3)
+        .filter_map(|triplet| {
+            let start = end + triplet[0];
+            end = start + triplet[1];
+            if start < 0 || triplet[1] < 0 || triplet[2] < 0 {
+                return None;
+            }
+            Some(StyleSpan {
+                start: start as usize,
+                end: end as usize,
+                style_id: triplet[2] as u64,
+            })
+        })
+        .collect()
+}
+
+/// The styles xi-core has defined with `def_style`, by id.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StyleTable {
+    styles: HashMap<u64, Style>,
+}
+
+impl StyleTable {
+    pub fn new() -> Self {
+        StyleTable::default()
+    }
+
+    pub fn define(&mut self, params: DefStyleParams) {
+        self.styles.insert(params.id, Style::from(params));
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Style> {
+        self.styles.get(&id)
+    }
+
+    /// Splits `text` into runs that each look the same, combining the styles
+    /// of overlapping spans with later spans on top. Spans of styles that
+    /// aren't defined, or that don't fall on character boundaries, are
+    /// ignored.
+    pub fn segments<'a>(&self, text: &'a str, styles: &[i64]) -> Vec<(&'a str, Style)> {
+        let spans: Vec<StyleSpan> = spans(styles)
+            .into_iter()
+            .filter(|span| self.styles.contains_key(&span.style_id))
+            .filter(|span| text.is_char_boundary(span.start.min(text.len())))
+            .filter(|span| text.is_char_boundary(span.end.min(text.len())))
+            .collect();
+
+        let mut bounds = vec![0, text.len()];
+        for span in &spans {
+            bounds.push(span.start.min(text.len()));
+            bounds.push(span.end.min(text.len()));
+        }
+        bounds.sort();
+        bounds.dedup();
+
+        let mut segments: Vec<(&'a str, Style)> = Vec::new();
+        let mut start = 0;
+        for pair in bounds.windows(2) {
+            let mut style = Style::default();
+            for span in spans
+                .iter()
+                .filter(|s| s.start <= pair[0] && pair[1] <= s.end)
+            {
+                style.merge(&self.styles[&span.style_id]);
+            }
+            // keep a run going while the style doesn't change
+            match segments.last() {
+                Some((_, last)) if *last == style => {
+                    segments.pop();
+                }
+                _ => start = pair[0],
+            }
+            segments.push((&text[start..pair[1]], style));
+        }
+        segments
+    }
+}
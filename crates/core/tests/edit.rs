@@ -0,0 +1,358 @@
+extern crate serde_json;
+extern crate zn_core;
+
+use serde_json::Value;
+use zn_core::messages::Edit::*;
+use zn_core::messages::{
+    CharsParams, ClientMessage, Edit, FindNextParams, FindParams, GestureParams, GestureType,
+    GotoLineParams, HighlightFindParams, LineRange, ReplaceParams, Request, RequestId,
+    ResizeParams, Response, ScrollParams, SelectionForFindParams, SelectionModifier,
+};
+
+fn v() -> String {
+    String::from("view-id-1")
+}
+
+/// The edit as xi-core reads it, from the `params` of an `edit` method.
+fn wire(edit: &Edit) -> Value {
+    serde_json::to_value(edit).unwrap()
+}
+
+fn expected(json: &str) -> Value {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn commands_without_params_use_xi_names() {
+    let commands = vec![
+        (InsertNewline { view_id: v() }, "insert_newline"),
+        (InsertTab { view_id: v() }, "insert_tab"),
+        (DeleteBackward { view_id: v() }, "delete_backward"),
+        (DeleteForward { view_id: v() }, "delete_forward"),
+        (DeleteWordBackward { view_id: v() }, "delete_word_backward"),
+        (DeleteWordForward { view_id: v() }, "delete_word_forward"),
+        (
+            DeleteToEndOfParagraph { view_id: v() },
+            "delete_to_end_of_paragraph",
+        ),
+        (
+            DeleteToBeginningOfLine { view_id: v() },
+            "delete_to_beginning_of_line",
+        ),
+        (MoveUp { view_id: v() }, "move_up"),
+        (
+            MoveUpAndModifySelection { view_id: v() },
+            "move_up_and_modify_selection",
+        ),
+        (MoveDown { view_id: v() }, "move_down"),
+        (
+            MoveDownAndModifySelection { view_id: v() },
+            "move_down_and_modify_selection",
+        ),
+        (MoveLeft { view_id: v() }, "move_left"),
+        (
+            MoveLeftAndModifySelection { view_id: v() },
+            "move_left_and_modify_selection",
+        ),
+        (MoveRight { view_id: v() }, "move_right"),
+        (
+            MoveRightAndModifySelection { view_id: v() },
+            "move_right_and_modify_selection",
+        ),
+        (MoveWordLeft { view_id: v() }, "move_word_left"),
+        (
+            MoveWordLeftAndModifySelection { view_id: v() },
+            "move_word_left_and_modify_selection",
+        ),
+        (MoveWordRight { view_id: v() }, "move_word_right"),
+        (
+            MoveWordRightAndModifySelection { view_id: v() },
+            "move_word_right_and_modify_selection",
+        ),
+        (
+            MoveToBeginningOfParagraph { view_id: v() },
+            "move_to_beginning_of_paragraph",
+        ),
+        (
+            MoveToBeginningOfParagraphAndModifySelection { view_id: v() },
+            "move_to_beginning_of_paragraph_and_modify_selection",
+        ),
+        (
+            MoveToEndOfParagraph { view_id: v() },
+            "move_to_end_of_paragraph",
+        ),
+        (
+            MoveToEndOfParagraphAndModifySelection { view_id: v() },
+            "move_to_end_of_paragraph_and_modify_selection",
+        ),
+        (
+            MoveToLeftEndOfLine { view_id: v() },
+            "move_to_left_end_of_line",
+        ),
+        (
+            MoveToLeftEndOfLineAndModifySelection { view_id: v() },
+            "move_to_left_end_of_line_and_modify_selection",
+        ),
+        (
+            MoveToRightEndOfLine { view_id: v() },
+            "move_to_right_end_of_line",
+        ),
+        (
+            MoveToRightEndOfLineAndModifySelection { view_id: v() },
+            "move_to_right_end_of_line_and_modify_selection",
+        ),
+        (
+            MoveToBeginningOfDocument { view_id: v() },
+            "move_to_beginning_of_document",
+        ),
+        (
+            MoveToBeginningOfDocumentAndModifySelection { view_id: v() },
+            "move_to_beginning_of_document_and_modify_selection",
+        ),
+        (
+            MoveToEndOfDocument { view_id: v() },
+            "move_to_end_of_document",
+        ),
+        (
+            MoveToEndOfDocumentAndModifySelection { view_id: v() },
+            "move_to_end_of_document_and_modify_selection",
+        ),
+        (ScrollPageUp { view_id: v() }, "scroll_page_up"),
+        (
+            ScrollPageUpAndModifySelection { view_id: v() },
+            "page_up_and_modify_selection",
+        ),
+        (ScrollPageDown { view_id: v() }, "scroll_page_down"),
+        (
+            ScrollPageDownAndModifySelection { view_id: v() },
+            "page_down_and_modify_selection",
+        ),
+        (SelectAll { view_id: v() }, "select_all"),
+        (AddSelectionAbove { view_id: v() }, "add_selection_above"),
+        (AddSelectionBelow { view_id: v() }, "add_selection_below"),
+        (CollapseSelections { view_id: v() }, "collapse_selections"),
+        (SelectionIntoLines { view_id: v() }, "selection_into_lines"),
+        (CancelOperation { view_id: v() }, "cancel_operation"),
+        (Undo { view_id: v() }, "undo"),
+        (Redo { view_id: v() }, "redo"),
+        (Yank { view_id: v() }, "yank"),
+        (Copy { view_id: v() }, "copy"),
+        (Cut { view_id: v() }, "cut"),
+        (Transpose { view_id: v() }, "transpose"),
+        (Uppercase { view_id: v() }, "uppercase"),
+        (Lowercase { view_id: v() }, "lowercase"),
+        (Capitalize { view_id: v() }, "capitalize"),
+        (Indent { view_id: v() }, "indent"),
+        (Outdent { view_id: v() }, "outdent"),
+        (Reindent { view_id: v() }, "reindent"),
+        (DuplicateLine { view_id: v() }, "duplicate_line"),
+        (IncreaseNumber { view_id: v() }, "increase_number"),
+        (DecreaseNumber { view_id: v() }, "decrease_number"),
+        (FindAll { view_id: v() }, "find_all"),
+        (ReplaceNext { view_id: v() }, "replace_next"),
+        (ReplaceAll { view_id: v() }, "replace_all"),
+        (
+            SelectionForReplace { view_id: v() },
+            "selection_for_replace",
+        ),
+    ];
+    for (edit, method) in commands {
+        let json = format!(r#"{{"method":"{}","view_id":"view-id-1"}}"#, method);
+        assert_eq!(wire(&edit), expected(&json), "{}", method);
+    }
+}
+
+#[test]
+fn commands_with_params_use_xi_shapes() {
+    let chars = || CharsParams {
+        chars: String::from("a"),
+    };
+    let next = || FindNextParams {
+        wrap_around: true,
+        allow_same: false,
+        modify_selection: SelectionModifier::AddRemovingCurrent,
+    };
+    let commands = vec![
+        (
+            Insert {
+                params: chars(),
+                view_id: v(),
+            },
+            r#"{"method":"insert","params":{"chars":"a"},"view_id":"view-id-1"}"#,
+        ),
+        (
+            Paste {
+                params: chars(),
+                view_id: v(),
+            },
+            r#"{"method":"paste","params":{"chars":"a"},"view_id":"view-id-1"}"#,
+        ),
+        (
+            Gesture {
+                params: GestureParams {
+                    line: 1,
+                    col: 2,
+                    ty: GestureType::PointSelect,
+                },
+                view_id: v(),
+            },
+            r#"{"method":"gesture","params":{"line":1,"col":2,"ty":"point_select"},"view_id":"view-id-1"}"#,
+        ),
+        (
+            GotoLine {
+                params: GotoLineParams { line: 7 },
+                view_id: v(),
+            },
+            r#"{"method":"goto_line","params":{"line":7},"view_id":"view-id-1"}"#,
+        ),
+        (
+            Scroll {
+                params: ScrollParams(0, 20),
+                view_id: v(),
+            },
+            r#"{"method":"scroll","params":[0,20],"view_id":"view-id-1"}"#,
+        ),
+        (
+            Find {
+                params: FindParams {
+                    chars: String::from("a"),
+                    case_sensitive: false,
+                    regex: true,
+                    whole_words: false,
+                },
+                view_id: v(),
+            },
+            r#"{"method":"find","params":{"chars":"a","case_sensitive":false,"regex":true,"whole_words":false},"view_id":"view-id-1"}"#,
+        ),
+        (
+            FindNext {
+                params: next(),
+                view_id: v(),
+            },
+            r#"{"method":"find_next","params":{"wrap_around":true,"allow_same":false,"modify_selection":"add_removing_current"},"view_id":"view-id-1"}"#,
+        ),
+        (
+            FindPrevious {
+                params: next(),
+                view_id: v(),
+            },
+            r#"{"method":"find_previous","params":{"wrap_around":true,"allow_same":false,"modify_selection":"add_removing_current"},"view_id":"view-id-1"}"#,
+        ),
+        (
+            HighlightFind {
+                params: HighlightFindParams { visible: true },
+                view_id: v(),
+            },
+            r#"{"method":"highlight_find","params":{"visible":true},"view_id":"view-id-1"}"#,
+        ),
+        (
+            SelectionForFind {
+                params: SelectionForFindParams {
+                    case_sensitive: true,
+                },
+                view_id: v(),
+            },
+            r#"{"method":"selection_for_find","params":{"case_sensitive":true},"view_id":"view-id-1"}"#,
+        ),
+        (
+            Replace {
+                params: ReplaceParams {
+                    chars: String::from("b"),
+                    preserve_case: false,
+                },
+                view_id: v(),
+            },
+            r#"{"method":"replace","params":{"chars":"b","preserve_case":false},"view_id":"view-id-1"}"#,
+        ),
+        (
+            Resize {
+                params: ResizeParams {
+                    width: 800,
+                    height: 600,
+                },
+                view_id: v(),
+            },
+            r#"{"method":"resize","params":{"width":800,"height":600},"view_id":"view-id-1"}"#,
+        ),
+        (
+            RequestLines {
+                params: LineRange(10, 30),
+                view_id: v(),
+            },
+            r#"{"method":"request_lines","params":[10,30],"view_id":"view-id-1"}"#,
+        ),
+        (
+            ToggleRecording {
+                params: None,
+                view_id: v(),
+            },
+            r#"{"method":"toggle_recording","params":null,"view_id":"view-id-1"}"#,
+        ),
+        (
+            ToggleRecording {
+                params: Some(String::from("macro")),
+                view_id: v(),
+            },
+            r#"{"method":"toggle_recording","params":"macro","view_id":"view-id-1"}"#,
+        ),
+        (
+            PlayRecording {
+                params: String::from("macro"),
+                view_id: v(),
+            },
+            r#"{"method":"play_recording","params":"macro","view_id":"view-id-1"}"#,
+        ),
+        (
+            ClearRecording {
+                params: String::from("macro"),
+                view_id: v(),
+            },
+            r#"{"method":"clear_recording","params":"macro","view_id":"view-id-1"}"#,
+        ),
+    ];
+    for (edit, json) in commands {
+        assert_eq!(wire(&edit), expected(json), "{}", json);
+    }
+}
+
+/// What the client sends is what it reads back, so edits can be logged
+/// and replayed.
+#[test]
+fn commands_round_trip() {
+    let edits = vec![
+        ScrollPageDownAndModifySelection { view_id: v() },
+        PlayRecording {
+            params: String::from("macro"),
+            view_id: v(),
+        },
+    ];
+    for edit in edits {
+        let json = serde_json::to_string(&edit).unwrap();
+        let read: Edit = serde_json::from_str(&json).unwrap();
+        assert_eq!(wire(&read), wire(&edit));
+    }
+}
+
+#[test]
+fn copy_and_cut_are_requests() {
+    let copy = Request::Copy(v()).into_message(RequestId(4));
+    assert_eq!(
+        serde_json::to_value(&copy).unwrap(),
+        expected(r#"{"method":"edit","id":4,"params":{"method":"copy","view_id":"view-id-1"}}"#)
+    );
+    let text = Request::Cut(v()).decode(Value::from("fn main"));
+    assert!(matches!(text, Ok(Response::Cut(Some(ref text))) if text == "fn main"));
+    // without a selection there's nothing to copy
+    let nothing = Request::Copy(v()).decode(Value::Null);
+    assert!(matches!(nothing, Ok(Response::Copy(None))));
+
+    // other edits don't expect an answer
+    let insert = ClientMessage::Edit {
+        id: None,
+        params: InsertNewline { view_id: v() },
+    };
+    assert_eq!(
+        serde_json::to_value(&insert).unwrap(),
+        expected(r#"{"method":"edit","params":{"method":"insert_newline","view_id":"view-id-1"}}"#)
+    );
+}
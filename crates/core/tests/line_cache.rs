@@ -0,0 +1,182 @@
+extern crate proptest;
+extern crate zn_core;
+
+use proptest::prelude::*;
+use zn_core::line_cache::LineCache;
+use zn_core::messages::{Line, OpType, UpdateOp};
+
+/// The straightforward reading of the update protocol, one slot per line.
+fn model_apply(old: &[Option<Line>], ops: &[UpdateOp]) -> Vec<Option<Line>> {
+    let mut new = Vec::new();
+    let mut old_ix = 0;
+    for op in ops {
+        match op.op {
+            OpType::Copy => {
+                let start = new.len();
+                for ix in old_ix..old_ix + op.n {
+                    new.push(old.get(ix).cloned().and_then(|line| line));
+                }
+                old_ix += op.n;
+                if let Some(ln) = op.first_line_number {
+                    model_renumber(&mut new[start..], ln as u64);
+                }
+            }
+            OpType::Skip => old_ix += op.n,
+            OpType::Invalidate => new.extend((0..op.n).map(|_| None)),
+            OpType::Insert => new.extend(op.lines.clone().unwrap().into_iter().map(Some)),
+        }
+    }
+    new
+}
+
+fn model_renumber(copied: &mut [Option<Line>], ln: u64) {
+    let mut delta = None;
+    for (ix, line) in copied.iter().enumerate() {
+        match line {
+            None => break,
+            Some(Line { ln: Some(old), .. }) => {
+                let new = if ix == 0 { ln } else { ln + 1 };
+                delta = Some(new as i64 - *old as i64);
+                break;
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(delta) = delta {
+        for line in copied.iter_mut().flatten() {
+            line.ln = line.ln.map(|old| (old as i64 + delta) as u64);
+        }
+    }
+}
+
+fn line() -> impl Strategy<Value = Line> {
+    ("[a-z]{0,8}", proptest::option::of(1u64..1000)).prop_map(|(text, ln)| Line {
+        cursor: None,
+        ln,
+        styles: Vec::new(),
+        text,
+    })
+}
+
+fn op() -> impl Strategy<Value = UpdateOp> {
+    prop_oneof![
+        (0usize..8, proptest::option::of(1usize..1000)).prop_map(|(n, ln)| UpdateOp {
+            op: OpType::Copy,
+            n,
+            lines: None,
+            first_line_number: ln,
+        }),
+        (0usize..8).prop_map(|n| UpdateOp {
+            op: OpType::Skip,
+            n,
+            lines: None,
+            first_line_number: None,
+        }),
+        (0usize..8).prop_map(|n| UpdateOp {
+            op: OpType::Invalidate,
+            n,
+            lines: None,
+            first_line_number: None,
+        }),
+        proptest::collection::vec(line(), 0..8).prop_map(|lines| UpdateOp {
+            op: OpType::Insert,
+            n: lines.len(),
+            lines: Some(lines),
+            first_line_number: None,
+        }),
+    ]
+}
+
+fn contents(cache: &LineCache) -> Vec<Option<Line>> {
+    cache.iter().map(|line| line.cloned()).collect()
+}
+
+proptest! {
+    #[test]
+    fn matches_reference_model(updates in proptest::collection::vec(proptest::collection::vec(op(), 0..12), 1..8)) {
+        let mut cache = LineCache::new();
+        let mut model: Vec<Option<Line>> = Vec::new();
+
+        for ops in updates {
+            model = model_apply(&model, &ops);
+            cache.apply(ops);
+
+            prop_assert_eq!(contents(&cache), model.clone());
+            prop_assert_eq!(cache.height(), model.len());
+            prop_assert_eq!(cache.is_empty(), model.is_empty());
+            for (ix, line) in model.iter().enumerate() {
+                prop_assert_eq!(cache.get(ix), line.as_ref());
+            }
+            prop_assert_eq!(cache.get(model.len()), None);
+
+            let mut resynced = LineCache::new();
+            resynced.apply(vec![ins(vec![("stale", Some(1))])]);
+            resynced.apply(cache.to_ops());
+            prop_assert_eq!(contents(&resynced), model.clone());
+
+            let len = model.len();
+            for &(start, end) in &[(0, len), (len / 3, len / 2), (len / 2, len + 5), (len, 0)] {
+                let range: Vec<_> = cache.range(start, end).map(|line| line.cloned()).collect();
+                let expected = model.get(start..end.min(len)).unwrap_or_default();
+                prop_assert_eq!(range, expected.to_vec());
+            }
+        }
+    }
+}
+
+fn ins(lines: Vec<(&str, Option<u64>)>) -> UpdateOp {
+    UpdateOp {
+        op: OpType::Insert,
+        n: lines.len(),
+        lines: Some(
+            lines
+                .into_iter()
+                .map(|(text, ln)| Line {
+                    cursor: None,
+                    ln,
+                    styles: Vec::new(),
+                    text: text.to_string(),
+                })
+                .collect(),
+        ),
+        first_line_number: None,
+    }
+}
+
+fn simple(op: OpType, n: usize, ln: Option<usize>) -> UpdateOp {
+    UpdateOp {
+        op,
+        n,
+        lines: None,
+        first_line_number: ln,
+    }
+}
+
+#[test]
+fn copy_renumbers_lines_after_an_insert() {
+    let mut cache = LineCache::new();
+    cache.apply(vec![ins(vec![("a", Some(1)), ("b", Some(2)), ("b'", None)])]);
+    cache.apply(vec![
+        ins(vec![("new", Some(1))]),
+        simple(OpType::Copy, 3, Some(2)),
+    ]);
+
+    let numbers: Vec<_> = cache.iter().map(|line| line.unwrap().ln).collect();
+    assert_eq!(numbers, vec![Some(1), Some(2), Some(3), None]);
+}
+
+#[test]
+fn skip_and_invalidate_keep_the_height() {
+    let mut cache = LineCache::new();
+    cache.apply(vec![simple(OpType::Invalidate, 1000, None)]);
+    cache.apply(vec![
+        simple(OpType::Copy, 10, None),
+        simple(OpType::Skip, 1, None),
+        ins(vec![("visible", Some(11))]),
+        simple(OpType::Copy, 989, None),
+    ]);
+
+    assert_eq!(cache.height(), 1000);
+    assert_eq!(cache.get(10).map(|line| line.text.as_str()), Some("visible"));
+    assert_eq!(cache.get(9), None);
+}
@@ -0,0 +1,46 @@
+extern crate uuid;
+extern crate zn_core;
+
+use uuid::Uuid;
+use zn_core::messages::{ClientMessage, Encoding, ServerMessage};
+
+/// Client messages are internally tagged, with `method` next to the params.
+#[test]
+fn client_messages_survive_binary_frames() {
+    let messages = [
+        r#"{"method":"client_started","params":{"encoding":"cbor"}}"#,
+        r#"{"method":"new_view","id":3,"params":{"file_path":"src/lib.rs"}}"#,
+        r#"{"method":"edit","params":{"method":"insert","params":{"chars":"a"},"view_id":"view-id-1"}}"#,
+        r#"{"method":"get_version"}"#,
+    ];
+    for json in messages.iter() {
+        let message = ClientMessage::from_json(json).unwrap();
+        let binary = message.to_binary().unwrap();
+        let decoded = ClientMessage::from_binary(&binary).unwrap();
+        assert_eq!(decoded.to_json().unwrap(), message.to_json().unwrap());
+    }
+}
+
+/// Server messages are adjacently tagged, and carry untagged notifications
+/// and raw `Value`s from xi-core.
+#[test]
+fn server_messages_survive_binary_frames() {
+    let messages = vec![
+        ServerMessage::Connected {
+            session_id: Uuid::new_v4(),
+            resumed: false,
+            encoding: Encoding::Cbor,
+        },
+        ServerMessage::from_xi_json(
+            r#"{"method":"scroll_to","params":{"col":4,"line":2,"view_id":"view-id-1"}}"#,
+        )
+        .unwrap(),
+        ServerMessage::from_xi_json(r#"{"id":0,"result":"view-id-1"}"#).unwrap(),
+        ServerMessage::from_xi_json(r#"{"jsonrpc":"2.0","numbers":[1,-2,0.5]}"#).unwrap(),
+    ];
+    for message in messages {
+        let binary = message.to_binary().unwrap();
+        let decoded = ServerMessage::from_binary(&binary).unwrap();
+        assert_eq!(decoded.to_json().unwrap(), message.to_json().unwrap());
+    }
+}
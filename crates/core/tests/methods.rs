@@ -0,0 +1,181 @@
+extern crate serde_json;
+extern crate zn_core;
+
+use zn_core::messages::{Method, RequestId, ServerMessage, WidthRequest};
+
+/// Reads a method as xi-core sends it.
+fn method(json: &str) -> Method {
+    match ServerMessage::from_xi_json(json).unwrap() {
+        ServerMessage::EditorMethod(method) => method,
+        other => panic!("not a method: {:?}", other),
+    }
+}
+
+#[test]
+fn def_style() {
+    let json = r#"{"method":"def_style","params":{"id":2,"fg_color":4294901760,"italic":true}}"#;
+    match method(json) {
+        Method::DefStyle(style) => {
+            assert_eq!(style.id, 2);
+            assert_eq!(style.fg_color, Some(0xffff_0000));
+            assert_eq!(style.bg_color, None);
+            assert_eq!(style.italic, Some(true));
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn theme_changed() {
+    let json = r#"{"method":"theme_changed","params":{"name":"InspiredGitHub","theme":{
+        "foreground":{"r":50,"g":51,"b":52,"a":255},"caret":null,"shadow":{"r":0,"g":0,"b":0,"a":16}}}}"#;
+    match method(json) {
+        Method::ThemeChanged { name, theme } => {
+            assert_eq!(name, "InspiredGitHub");
+            let foreground = theme.foreground.unwrap();
+            assert_eq!((foreground.r, foreground.g, foreground.b), (50, 51, 52));
+            assert!(theme.caret.is_none());
+            assert!(theme.background.is_none());
+            assert_eq!(theme.shadow.unwrap().a, 16);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn find_status() {
+    let json = r#"{"method":"find_status","params":{"view_id":"view-id-1","queries":[
+        {"id":1,"chars":"a","case_sensitive":false,"is_regex":false,"whole_words":true,"matches":3,"lines":[1,4]},
+        {"id":2,"chars":null,"case_sensitive":null,"is_regex":null,"whole_words":null,"matches":0}]}}"#;
+    match method(json) {
+        Method::FindStatus { queries, view_id } => {
+            assert_eq!(view_id, "view-id-1");
+            assert_eq!(queries.len(), 2);
+            assert_eq!(queries[0].chars.as_deref(), Some("a"));
+            assert_eq!(queries[0].whole_words, Some(true));
+            assert_eq!(queries[0].matches, 3);
+            assert_eq!(queries[0].lines, vec![1, 4]);
+            assert_eq!(queries[1].chars, None);
+            assert!(queries[1].lines.is_empty());
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn replace_status() {
+    let json = r#"{"method":"replace_status","params":{"view_id":"view-id-1",
+        "status":{"chars":"b","preserve_case":false,"is_regex":null}}}"#;
+    match method(json) {
+        Method::ReplaceStatus { status, view_id } => {
+            assert_eq!(view_id, "view-id-1");
+            assert_eq!(status.chars, "b");
+            assert_eq!(status.preserve_case, Some(false));
+            assert_eq!(status.is_regex, None);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn status_items() {
+    let add = r#"{"method":"add_status_item","params":{"view_id":"view-id-1",
+        "source":"spellcheck","key":"errors","value":"3 errors","alignment":"left"}}"#;
+    match method(add) {
+        Method::AddStatusItem {
+            alignment,
+            key,
+            source,
+            value,
+            view_id,
+        } => {
+            assert_eq!(alignment, "left");
+            assert_eq!(key, "errors");
+            assert_eq!(source, "spellcheck");
+            assert_eq!(value, "3 errors");
+            assert_eq!(view_id, "view-id-1");
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let update = r#"{"method":"update_status_item","params":{"view_id":"view-id-1",
+        "key":"errors","value":"none"}}"#;
+    match method(update) {
+        Method::UpdateStatusItem { key, value, .. } => {
+            assert_eq!(key, "errors");
+            assert_eq!(value, "none");
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let remove =
+        r#"{"method":"remove_status_item","params":{"view_id":"view-id-1","key":"errors"}}"#;
+    match method(remove) {
+        Method::RemoveStatusItem { key, view_id } => {
+            assert_eq!(key, "errors");
+            assert_eq!(view_id, "view-id-1");
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn show_hover() {
+    let json = r#"{"method":"show_hover","params":{"view_id":"view-id-1","request_id":3,
+        "result":"fn main()"}}"#;
+    match method(json) {
+        Method::ShowHover {
+            request_id,
+            result,
+            view_id,
+        } => {
+            assert_eq!(request_id, 3);
+            assert_eq!(result, "fn main()");
+            assert_eq!(view_id, "view-id-1");
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+/// xi-core sends `measure_width` as a request, with its id next to the
+/// method and the requests as bare params.
+#[test]
+fn measure_width_keeps_its_id() {
+    let json = r#"{"method":"measure_width","id":5,"params":[{"id":0,"strings":["a","bb"]}]}"#;
+    let measure = method(json);
+    assert_eq!(measure.view_id(), None);
+    match &measure {
+        Method::MeasureWidth { id, requests } => {
+            assert_eq!(*id, RequestId(5));
+            let expected = WidthRequest {
+                id: 0,
+                strings: vec![String::from("a"), String::from("bb")],
+            };
+            assert_eq!(requests, &vec![expected]);
+        }
+        other => panic!("{:?}", other),
+    }
+
+    // and gets to the client with the id intact
+    let sent = ServerMessage::EditorMethod(measure).to_json().unwrap();
+    match ServerMessage::from_json(&sent).unwrap() {
+        ServerMessage::EditorMethod(Method::MeasureWidth { id, .. }) => {
+            assert_eq!(id, RequestId(5))
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unknown_methods_keep_their_view() {
+    let json = r#"{"method":"new_thing","params":{"view_id":"view-id-1","x":1}}"#;
+    let unknown = method(json);
+    assert_eq!(unknown.view_id().map(String::as_str), Some("view-id-1"));
+    match unknown {
+        Method::Unknown { method, params } => {
+            assert_eq!(method, "new_thing");
+            assert_eq!(params["x"], 1);
+        }
+        other => panic!("{:?}", other),
+    }
+}
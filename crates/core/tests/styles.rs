@@ -0,0 +1,157 @@
+extern crate zn_core;
+
+use zn_core::messages::DefStyleParams;
+use zn_core::styles::{spans, Style, StyleSpan, StyleTable};
+
+fn span(start: usize, end: usize, style_id: u64) -> StyleSpan {
+    StyleSpan {
+        start,
+        end,
+        style_id,
+    }
+}
+
+fn table(ids: &[u64]) -> StyleTable {
+    let mut table = StyleTable::new();
+    for &id in ids {
+        table.define(DefStyleParams {
+            id,
+            fg_color: Some(id as u32),
+            bg_color: None,
+            weight: None,
+            italic: None,
+            underline: None,
+        });
+    }
+    table
+}
+
+fn fg(id: u32) -> Style {
+    Style {
+        fg_color: Some(id),
+        ..Style::default()
+    }
+}
+
+#[test]
+fn starts_are_relative_to_the_previous_end() {
+    // `let x = 1;` with `let` and `1` styled
+    assert_eq!(
+        spans(&[0, 3, 2, 5, 1, 3]),
+        vec![span(0, 3, 2), span(8, 9, 3)]
+    );
+}
+
+#[test]
+fn zero_offsets_make_adjacent_spans() {
+    assert_eq!(
+        spans(&[2, 2, 1, 0, 3, 4, 0, 1, 5]),
+        vec![span(2, 4, 1), span(4, 7, 4), span(7, 8, 5)]
+    );
+}
+
+#[test]
+fn negative_offsets_overlap_the_previous_span() {
+    // a selection over the whole of a keyword and the space after it
+    assert_eq!(
+        spans(&[4, 3, 2, -3, 4, 7]),
+        vec![span(4, 7, 2), span(4, 8, 7)]
+    );
+}
+
+#[test]
+fn spans_before_the_line_are_dropped_but_still_move_the_end() {
+    assert_eq!(spans(&[-2, 3, 1, 1, 2, 6]), vec![span(2, 4, 6)]);
+}
+
+#[test]
+fn incomplete_triplets_are_ignored() {
+    assert_eq!(spans(&[0, 3, 2, 5, 1]), vec![span(0, 3, 2)]);
+    assert!(spans(&[]).is_empty());
+}
+
+#[test]
+fn segments_end_with_the_unstyled_rest() {
+    let text = "let x = 1;";
+    let segments = table(&[2]).segments(text, &[0, 3, 2]);
+    assert_eq!(
+        segments,
+        vec![("let", fg(2)), (" x = 1;", Style::default())]
+    );
+}
+
+#[test]
+fn segments_cover_the_whole_line() {
+    let text = "let x = 1;";
+    let segments = table(&[2, 3]).segments(text, &[0, 3, 2, 5, 1, 3]);
+    assert_eq!(
+        segments,
+        vec![
+            ("let", fg(2)),
+            (" x = ", Style::default()),
+            ("1", fg(3)),
+            (";", Style::default()),
+        ]
+    );
+    let joined: String = segments.iter().map(|(text, _)| *text).collect();
+    assert_eq!(joined, text);
+}
+
+#[test]
+fn later_spans_go_on_top() {
+    let mut table = table(&[2]);
+    table.define(DefStyleParams {
+        id: 7,
+        fg_color: None,
+        bg_color: Some(0xff00_00ff),
+        weight: None,
+        italic: Some(true),
+        underline: None,
+    });
+    let segments = table.segments("let x", &[0, 3, 2, -3, 4, 7]);
+    let both = Style {
+        fg_color: Some(2),
+        bg_color: Some(0xff00_00ff),
+        italic: Some(true),
+        ..Style::default()
+    };
+    let selected = Style {
+        bg_color: Some(0xff00_00ff),
+        italic: Some(true),
+        ..Style::default()
+    };
+    assert_eq!(
+        segments,
+        vec![("let", both), (" ", selected), ("x", Style::default())]
+    );
+}
+
+#[test]
+fn undefined_styles_are_unstyled() {
+    let segments = table(&[]).segments("let", &[0, 3, 2]);
+    assert_eq!(segments, vec![("let", Style::default())]);
+}
+
+#[test]
+fn offsets_are_in_bytes_of_multi_byte_chars() {
+    // `é` is two bytes and `€` three
+    let text = "é€x";
+    let segments = table(&[1, 2]).segments(text, &[0, 2, 1, 0, 3, 2]);
+    assert_eq!(
+        segments,
+        vec![("é", fg(1)), ("€", fg(2)), ("x", Style::default())]
+    );
+}
+
+#[test]
+fn spans_inside_a_char_are_ignored() {
+    let text = "é€x";
+    let segments = table(&[1]).segments(text, &[1, 3, 1]);
+    assert_eq!(segments, vec![(text, Style::default())]);
+}
+
+#[test]
+fn spans_past_the_end_are_cut_off() {
+    let segments = table(&[1]).segments("ab", &[1, 5, 1]);
+    assert_eq!(segments, vec![("a", Style::default()), ("b", fg(1))]);
+}
@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong between a client's websocket and its xi-core.
+#[derive(Debug)]
+pub enum BridgeError {
+    /// The connection broke before the handshake.
+    Io(io::Error),
+    /// The websocket handshake failed.
+    Handshake(tungstenite::Error),
+    /// Reading from or writing to the websocket failed.
+    Socket(tungstenite::Error),
+    /// The client sent a frame that isn't a `ClientMessage`. The client is
+    /// told with a `ServerError`, the connection stays up.
+    BadFrame { reason: String, content: String },
+    /// xi-core sent something that isn't JSON.
+    BadCoreMessage(serde_json::Error),
+    /// A message couldn't be serialized.
+    Encode(serde_json::Error),
+    /// A message couldn't be encoded for a client that asked for binary frames.
+    EncodeBinary(serde_cbor::Error),
+    /// xi-core stopped taking messages.
+    CoreClosed,
+}
+
+impl BridgeError {
+    /// Whether the connection can't go on after this.
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            BridgeError::BadFrame { .. }
+                | BridgeError::BadCoreMessage(_)
+                | BridgeError::Encode(_)
+                | BridgeError::EncodeBinary(_)
+        )
+    }
+}
+
+impl fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BridgeError::Io(e) => write!(f, "connection failed: {}", e),
+            BridgeError::Handshake(e) => write!(f, "websocket handshake failed: {}", e),
+            BridgeError::Socket(e) => write!(f, "websocket failed: {}", e),
+            BridgeError::BadFrame { reason, .. } => write!(f, "bad frame from client: {}", reason),
+            BridgeError::BadCoreMessage(e) => write!(f, "bad message from xi-core: {}", e),
+            BridgeError::Encode(e) => write!(f, "couldn't encode message: {}", e),
+            BridgeError::EncodeBinary(e) => write!(f, "couldn't encode message: {}", e),
+            BridgeError::CoreClosed => write!(f, "xi-core stopped"),
+        }
+    }
+}
+
+impl Error for BridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BridgeError::Io(e) => Some(e),
+            BridgeError::Handshake(e) | BridgeError::Socket(e) => Some(e),
+            BridgeError::BadCoreMessage(e) | BridgeError::Encode(e) => Some(e),
+            BridgeError::EncodeBinary(e) => Some(e),
+            BridgeError::BadFrame { .. } | BridgeError::CoreClosed => None,
+        }
+    }
+}
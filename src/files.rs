@@ -0,0 +1,225 @@
+//! The file browsing API, which the server answers itself since xi-core has
+//! no notion of a workspace.
+use log::warn;
+use serde::Serialize;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use zn_core::messages::{
+    DirectoryListing, FileKind, FileStat, ListDirectoryParams, Notification, RequestId, RpcError,
+    ServerMessage, StatParams,
+};
+
+/// The most entries a listing holds, so listing a huge tree doesn't stall
+/// the connection.
+const MAX_ENTRIES: usize = 20_000;
+
+/// The error code of a failed file request, following JSON-RPC's range for
+/// server errors.
+const FILE_ERROR: i64 = -32000;
+
+/// Lists `params.path`, recursing if asked to. Entries are sorted by path.
+pub fn list_directory(
+    workspace: &Path,
+    params: &ListDirectoryParams,
+) -> Result<DirectoryListing, RpcError> {
+    let dir = in_workspace(workspace, &params.path, true)?;
+    let mut entries = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    let mut truncated = false;
+    while let Some(relative) = pending.pop() {
+        let read_dir = match fs::read_dir(dir.join(&relative)) {
+            Ok(read_dir) => read_dir,
+            // only the directory asked for has to be readable
+            Err(e) if relative.as_os_str().is_empty() => return Err(file_error(&dir, e)),
+            Err(e) => {
+                warn!("Skipping {}: {}", dir.join(&relative).display(), e);
+                continue;
+            }
+        };
+        for entry in read_dir.filter_map(Result::ok) {
+            let name = entry.file_name();
+            if params.recursive && name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if entries.len() == MAX_ENTRIES {
+                truncated = true;
+                pending.clear();
+                break;
+            }
+            let path = relative.join(&name);
+            // symlinks are listed but not followed, they could loop
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let stat = file_stat(&path, &metadata);
+            if params.recursive && stat.kind == FileKind::Directory {
+                pending.push(path);
+            }
+            entries.push(stat);
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(DirectoryListing {
+        path: dir.to_string_lossy().into_owned(),
+        entries,
+        truncated,
+    })
+}
+
+pub fn stat(workspace: &Path, params: &StatParams) -> Result<FileStat, RpcError> {
+    let path = in_workspace(workspace, &params.path, false)?;
+    let metadata = fs::symlink_metadata(&path).map_err(|e| file_error(&path, e))?;
+    Ok(file_stat(&path, &metadata))
+}
+
+/// `path` taken from the workspace, as long as it stays inside it: `..`,
+/// absolute paths and symlinks leading out of the workspace are refused. The
+/// last component is only followed with `follow`, so symlinks can be stat'ed.
+fn in_workspace(workspace: &Path, path: &str, follow: bool) -> Result<PathBuf, RpcError> {
+    let root = workspace
+        .canonicalize()
+        .map_err(|e| file_error(workspace, e))?;
+    let joined = root.join(path);
+    let resolved = match (joined.parent(), joined.file_name()) {
+        (Some(parent), Some(name)) if !follow => parent.canonicalize().map(|p| p.join(name)),
+        _ => joined.canonicalize(),
+    }
+    .map_err(|e| file_error(&joined, e))?;
+    if resolved.starts_with(&root) {
+        Ok(resolved)
+    } else {
+        Err(RpcError {
+            code: FILE_ERROR,
+            message: format!("{}: outside the workspace", joined.display()),
+            data: None,
+        })
+    }
+}
+
+/// The answer to request `id`, in the shape xi-core answers requests.
+pub fn response<T: Serialize>(id: RequestId, result: Result<T, RpcError>) -> ServerMessage {
+    let notification = match result.map(serde_json::to_value) {
+        Ok(Ok(result)) => Notification::Result { id, result },
+        Ok(Err(e)) => Notification::Error {
+            id,
+            error: RpcError {
+                code: FILE_ERROR,
+                message: e.to_string(),
+                data: None,
+            },
+        },
+        Err(error) => Notification::Error { id, error },
+    };
+    ServerMessage::EditorNotification(notification)
+}
+
+fn file_stat(path: &Path, metadata: &Metadata) -> FileStat {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_symlink() {
+        FileKind::Symlink
+    } else if file_type.is_dir() {
+        FileKind::Directory
+    } else if file_type.is_file() {
+        FileKind::File
+    } else {
+        FileKind::Other
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs());
+    FileStat {
+        path: path.to_string_lossy().into_owned(),
+        kind,
+        size: metadata.len(),
+        modified,
+    }
+}
+
+fn file_error(path: &Path, e: io::Error) -> RpcError {
+    RpcError {
+        code: FILE_ERROR,
+        message: format!("{}: {}", path.display(), e),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A fresh workspace for one test, holding `src/lib.rs`, with a secret
+    /// next to it. Both go with the directory returned along with it.
+    fn workspace() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("project/src")).unwrap();
+        fs::write(dir.path().join("project/src/lib.rs"), "").unwrap();
+        fs::write(dir.path().join("secret"), "").unwrap();
+        let workspace = dir.path().join("project");
+        (dir, workspace)
+    }
+
+    fn stat_path(workspace: &Path, path: &str) -> Result<FileStat, RpcError> {
+        let params = StatParams {
+            path: String::from(path),
+        };
+        stat(workspace, &params)
+    }
+
+    fn list(workspace: &Path, path: &str) -> Result<DirectoryListing, RpcError> {
+        let params = ListDirectoryParams {
+            path: String::from(path),
+            recursive: true,
+        };
+        list_directory(workspace, &params)
+    }
+
+    #[test]
+    fn paths_inside_the_workspace_are_answered() {
+        let (_dir, workspace) = workspace();
+        assert_eq!(
+            stat_path(&workspace, "src/lib.rs").unwrap().kind,
+            FileKind::File
+        );
+        assert_eq!(
+            stat_path(&workspace, "src/../src").unwrap().kind,
+            FileKind::Directory
+        );
+
+        let listing = list(&workspace, "").unwrap();
+        let paths: Vec<_> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["src", "src/lib.rs"]);
+    }
+
+    #[test]
+    fn paths_outside_the_workspace_are_refused() {
+        let (_dir, workspace) = workspace();
+        let secret = workspace.join("../secret");
+        for path in &["../secret", "src/../../secret", secret.to_str().unwrap()] {
+            let error = stat_path(&workspace, path).unwrap_err();
+            assert_eq!(error.code, FILE_ERROR);
+            assert!(error.message.ends_with("outside the workspace"));
+        }
+        assert_eq!(list(&workspace, "..").unwrap_err().code, FILE_ERROR);
+        assert_eq!(list(&workspace, "/").unwrap_err().code, FILE_ERROR);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_workspace_are_not_followed() {
+        let (_dir, workspace) = workspace();
+        std::os::unix::fs::symlink(workspace.join(".."), workspace.join("up")).unwrap();
+        assert_eq!(stat_path(&workspace, "up").unwrap().kind, FileKind::Symlink);
+        assert_eq!(
+            stat_path(&workspace, "up/secret").unwrap_err().code,
+            FILE_ERROR
+        );
+        assert_eq!(list(&workspace, "up").unwrap_err().code, FILE_ERROR);
+    }
+}
@@ -0,0 +1,190 @@
+//! Serves the client itself: the HTML shell, its stylesheet and the
+//! wasm-bindgen bundle. Shares the listener with the websocket, which lives
+//! under `/ws/`.
+//!
+//! By default files are read from `Config::static_dir` on every request, so a
+//! rebuilt bundle shows up on reload. With the `embed-client` feature they are
+//! compiled into the binary instead, from `pkg/` as left by `bin/build.sh`.
+//! Either way the HTML shell is told the websocket endpoint to connect to.
+use async_std::io::{self, BufReader};
+use async_std::net::TcpStream;
+use async_std::prelude::*;
+use async_std::task;
+use log::info;
+use std::borrow::Cow;
+use std::path::{Component, Path};
+use std::time::Duration;
+
+/// The longest request line we'll wait on before giving up on it.
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+
+#[cfg(feature = "embed-client")]
+const EMBEDDED: &[(&str, &[u8])] = &[
+    ("index.html", include_bytes!("../pkg/index.html")),
+    ("style.css", include_bytes!("../pkg/style.css")),
+    ("bundle.js", include_bytes!("../pkg/bundle.js")),
+    (
+        "zn_client_bg.wasm",
+        include_bytes!("../pkg/zn_client_bg.wasm"),
+    ),
+];
+
+/// Whether the request on `stream` is for the websocket. Only peeks at the
+/// request line, so the handshake can still read the whole request.
+pub async fn is_websocket_request(stream: &TcpStream) -> io::Result<bool> {
+    let mut buf = vec![0; MAX_REQUEST_LINE];
+    let mut seen = 0;
+    loop {
+        let n = stream.peek(&mut buf).await?;
+        if let Some(end) = buf[..n].iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&buf[..end]);
+            return Ok(matches!(request_path(&line), Some(path) if path.starts_with("/ws")));
+        }
+        // closed before a full line, or the line is too long to be ours
+        if n == 0 || n == buf.len() {
+            return Ok(false);
+        }
+        // peeking again returns right away until more of the line arrives
+        if n == seen {
+            task::sleep(Duration::from_millis(5)).await;
+        }
+        seen = n;
+    }
+}
+
+/// Answers one HTTP request with a file of the client, then closes the connection.
+pub async fn serve(stream: TcpStream, static_dir: &Path, endpoint: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // nothing in the headers changes the response, skip to the end of them
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let method = request_line.split_whitespace().next().unwrap_or_default();
+    let file = request_path(&request_line).and_then(file_name);
+    let (status, content_type, body) = match (method, file) {
+        ("GET", Some(file)) | ("HEAD", Some(file)) => match read_asset(file, static_dir).await {
+            Some(body) if file == "index.html" => (
+                "200 OK",
+                content_type(file),
+                Cow::Owned(with_endpoint(&body, endpoint)),
+            ),
+            Some(body) => ("200 OK", content_type(file), body),
+            None => not_found(),
+        },
+        ("GET", None) | ("HEAD", None) => not_found(),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            Cow::Borrowed(&b"Method Not Allowed"[..]),
+        ),
+    };
+    info!("{} {}", request_line.trim_end(), status);
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let mut stream = &stream;
+    stream.write_all(head.as_bytes()).await?;
+    if method != "HEAD" {
+        stream.write_all(&body).await?;
+    }
+    stream.flush().await
+}
+
+fn not_found() -> (&'static str, &'static str, Cow<'static, [u8]>) {
+    (
+        "404 Not Found",
+        "text/plain",
+        Cow::Borrowed(&b"Not Found"[..]),
+    )
+}
+
+/// The path of a request line like `GET /style.css HTTP/1.1`.
+fn request_path(request_line: &str) -> Option<&str> {
+    request_line.split_whitespace().nth(1)
+}
+
+/// The file a request path asks for, relative to the static dir. Paths that
+/// would leave it are refused.
+fn file_name(path: &str) -> Option<&str> {
+    let path = path.split(&['?', '#'][..]).next().unwrap_or_default();
+    let file = match path.trim_start_matches('/') {
+        "" => "index.html",
+        file => file,
+    };
+    let escapes = Path::new(file)
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)));
+    if escapes {
+        None
+    } else {
+        Some(file)
+    }
+}
+
+/// Sets `ZN_ENDPOINT` in the HTML shell, for the client to connect to.
+fn with_endpoint(html: &[u8], endpoint: &str) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    // a JSON string is a JS one too, and with `</` escaped it can't end the
+    // script early
+    let endpoint = serde_json::to_string(endpoint)
+        .unwrap_or_default()
+        .replace("</", "<\\/");
+    let script = format!("<script>window.ZN_ENDPOINT = {};</script>\n", endpoint);
+    let at = html.find("</head>").unwrap_or(0);
+    format!("{}{}{}", &html[..at], script, &html[at..]).into_bytes()
+}
+
+fn content_type(file: &str) -> &'static str {
+    match Path::new(file).extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        // `WebAssembly.instantiateStreaming` refuses anything else
+        Some("wasm") => "application/wasm",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(feature = "embed-client")]
+async fn read_asset(file: &str, _static_dir: &Path) -> Option<Cow<'static, [u8]>> {
+    EMBEDDED
+        .iter()
+        .find(|(name, _)| *name == file)
+        .map(|(_, body)| Cow::Borrowed(*body))
+}
+
+#[cfg(not(feature = "embed-client"))]
+async fn read_asset(file: &str, static_dir: &Path) -> Option<Cow<'static, [u8]>> {
+    let path = async_std::path::PathBuf::from(static_dir.join(file));
+    async_std::fs::read(path).await.ok().map(Cow::Owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_shell_is_told_the_endpoint() {
+        let html = b"<html><head><title>Zinc</title></head><body></body></html>";
+        let html = with_endpoint(html, "ws://127.0.0.1:8080/ws/");
+        assert_eq!(
+            String::from_utf8(html).unwrap(),
+            "<html><head><title>Zinc</title>\
+             <script>window.ZN_ENDPOINT = \"ws://127.0.0.1:8080/ws/\";</script>\n\
+             </head><body></body></html>"
+        );
+        let html = with_endpoint(b"<head></head>", "</script>");
+        assert!(String::from_utf8(html).unwrap().contains("\"<\\/script>\""));
+    }
+}
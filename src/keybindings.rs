@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use zn_core::messages::Keybinding;
+
+/// The layout of `keybindings.toml`, or the equivalent `keybindings.json`:
+///
+/// ```toml
+/// [[bindings]]
+/// keys = "C-x C-s"
+/// command = "save"
+/// ```
+#[derive(Debug, Deserialize)]
+struct KeybindingFile {
+    #[serde(default)]
+    bindings: Vec<Keybinding>,
+}
+
+/// `$XDG_CONFIG_HOME/zn`, falling back to `~/.config/zn`.
+pub fn default_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("zn"))
+}
+
+/// Reads the user's keybindings from `config_dir`, preferring TOML over JSON.
+/// Having neither file is not an error, there are just no overrides.
+pub fn load(config_dir: &Path) -> io::Result<Vec<Keybinding>> {
+    let toml_path = config_dir.join("keybindings.toml");
+    let json_path = config_dir.join("keybindings.json");
+
+    let file: KeybindingFile = if toml_path.exists() {
+        toml::from_str(&fs::read_to_string(&toml_path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else if json_path.exists() {
+        serde_json::from_str(&fs::read_to_string(&json_path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else {
+        return Ok(Vec::new());
+    };
+
+    Ok(file.bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn loads_toml() {
+        let dir = TempDir::new().unwrap();
+        let toml = r#"
+            [[bindings]]
+            keys = "C-x C-s"
+            command = "save"
+
+            [[bindings]]
+            keys = "enter"
+            command = "find_all"
+            when = "find_bar_focused"
+        "#;
+        fs::write(dir.path().join("keybindings.toml"), toml).unwrap();
+
+        let bindings = load(dir.path()).unwrap();
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(bindings[0].keys, "C-x C-s");
+        assert_eq!(bindings[0].command, "save");
+        assert_eq!(bindings[0].when, None);
+        assert_eq!(bindings[1].when.as_deref(), Some("find_bar_focused"));
+    }
+
+    #[test]
+    fn loads_json_without_toml() {
+        let dir = TempDir::new().unwrap();
+        let json = r#"{"bindings": [{"keys": "ctrl+k", "command": "undo"}]}"#;
+        fs::write(dir.path().join("keybindings.json"), json).unwrap();
+
+        let bindings = load(dir.path()).unwrap();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].keys, "ctrl+k");
+        assert_eq!(bindings[0].command, "undo");
+
+        // TOML wins when there are both
+        fs::write(dir.path().join("keybindings.toml"), "").unwrap();
+        assert!(load(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_files_are_no_bindings() {
+        let dir = TempDir::new().unwrap();
+        assert!(load(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("keybindings.toml"),
+            "[[bindings]]\nkeys = 1",
+        )
+        .unwrap();
+        let e = load(dir.path()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
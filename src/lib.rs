@@ -2,72 +2,375 @@ extern crate tungstenite;
 extern crate zn_core;
 use async_std::net::{TcpListener, TcpStream};
 use async_std::task;
-use futures::{AsyncReadExt, SinkExt, StreamExt, TryFutureExt};
-use log::info;
-use std::env;
-use std::sync::{Arc, Mutex};
+use async_tungstenite::WebSocketStream;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{select, SinkExt, StreamExt};
+use log::{debug, info, log_enabled, warn, Level};
+use std::path::PathBuf;
+use std::sync::Arc;
 use tungstenite::Message;
-use zn_core::messages::{ClientMessage, ServerMessage};
+use zn_core::messages::{ClientMessage, ClientStartedParams, Encoding, ServerMessage};
 
+use crate::error::BridgeError;
+use crate::session::{Session, Sessions};
+
+mod error;
+mod files;
+mod http;
+mod keybindings;
+mod session;
 mod xi;
 
-pub async fn start_websocket_server() -> Result<(), std::io::Error> {
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+type WsSink = SplitSink<WebSocketStream<TcpStream>, Message>;
+type WsStream = SplitStream<WebSocketStream<TcpStream>>;
+
+/// How the server is run, usually taken from the command line.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The address to listen on, e.g. `127.0.0.1:8080`.
+    pub addr: String,
+    /// Where xi-core and zn read their configuration from.
+    pub config_dir: Option<PathBuf>,
+    /// Files every client opens when it connects.
+    pub files: Vec<PathBuf>,
+    /// The directory file requests are relative to, for quick open.
+    pub workspace: PathBuf,
+    /// Whether every client shares one xi-core, editing the same buffers
+    /// together, instead of each getting its own.
+    pub shared: bool,
+    /// Where the client's HTML, stylesheet and wasm bundle are served from,
+    /// unless they were embedded with the `embed-client` feature.
+    pub static_dir: PathBuf,
+}
+
+impl Config {
+    /// The URL the client is served from.
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// The websocket URL clients should connect to.
+    pub fn endpoint(&self) -> String {
+        format!("ws://{}/ws/", self.addr)
+    }
+
+    fn config_dir(&self) -> Option<PathBuf> {
+        self.config_dir
+            .clone()
+            .or_else(keybindings::default_config_dir)
+    }
+}
 
+pub async fn start_websocket_server(config: Config) -> Result<(), std::io::Error> {
     // Create the event loop and TCP listener we'll accept connections on.
-    let try_socket = TcpListener::bind(&addr).await;
-    let listener = try_socket.expect("Failed to bind");
-    info!("Listening on: {}", addr);
+    let listener = TcpListener::bind(&config.addr).await?;
+    info!("Listening on: {}", config.addr);
 
+    let config = Arc::new(config);
+    let sessions = Arc::new(Sessions::default());
     while let Ok((stream, _)) = listener.accept().await {
-        task::spawn(accept_connection(stream));
+        task::spawn(handle_connection(
+            stream,
+            Arc::clone(&config),
+            Arc::clone(&sessions),
+        ));
     }
 
     Ok(())
 }
 
-async fn accept_connection(stream: TcpStream) {
-    let (xi_write_from_client, xi_read_to_client, _) = xi::start_xi_core();
+/// Upgrades websocket requests, and answers everything else with the client's files.
+async fn handle_connection(stream: TcpStream, config: Arc<Config>, sessions: Arc<Sessions>) {
+    match http::is_websocket_request(&stream).await {
+        Ok(true) => accept_connection(stream, config, sessions).await,
+        Ok(false) => {
+            if let Err(e) = http::serve(stream, &config.static_dir, &config.endpoint()).await {
+                warn!("Failed to serve a request: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to read a request: {}", e),
+    }
+}
 
-    let addr = stream
-        .peer_addr()
-        .expect("connected streams should have a peer address");
+async fn accept_connection(stream: TcpStream, config: Arc<Config>, sessions: Arc<Sessions>) {
+    let addr = match stream.peer_addr() {
+        Ok(addr) => addr,
+        Err(e) => {
+            warn!("Dropping connection: {}", BridgeError::Io(e));
+            return;
+        }
+    };
     info!("Peer address: {}", addr);
 
-    let (mut ws_read, mut ws_write) = async_tungstenite::accept_async(stream)
-        .await
-        .expect("Error during the websocket handshake occurred")
-        .split();
-
+    let (mut ws_sink, mut ws_stream) = match async_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws.split(),
+        Err(e) => {
+            warn!(
+                "Dropping connection from {}: {}",
+                addr,
+                BridgeError::Handshake(e)
+            );
+            return;
+        }
+    };
     info!("New WebSocket connection: {}", addr);
 
-    // Read WebSocket and send 2 XI
-    let h1 = std::thread::spawn(move || loop {
-        if let Some(msg) = async_std::task::block_on(ws_write.next()) {
-            let msg: Message = msg.expect("ws_write.next() msg.expect");
-            let msg_txt = msg.to_text().unwrap();
-            info!("Raw msg.to_text() = {}", msg_txt);
-            let js_msg = ClientMessage::from_json(msg.to_text().unwrap()).unwrap();
-            info!("Sending message to XI: {:?}", js_msg.to_json());
-            xi_write_from_client
-                .0
-                .send(js_msg.to_json().unwrap())
-                .unwrap();
-        }
-    });
-
-    // Read XI and send 2 WebSocket
-    let h2 = std::thread::spawn(move || loop {
-        if let Ok(msg) = xi_read_to_client.0.recv() {
-            info!("Sending message to client {}", msg);
-            let repr = ServerMessage::from_xi_json(&msg).unwrap();
-            // send to client
-            async_std::task::block_on(
-                ws_read.send(tungstenite::Message::Text(repr.to_json().unwrap())),
-            )
-            .unwrap();
-        }
-    });
+    // the client opens with `resume` if it had a session, or `client_started`
+    let config_dir = config.config_dir();
+    let first = match ws_stream.next().await {
+        Some(Ok(msg)) => client_message(msg, &config_dir),
+        Some(Err(e)) => Err(BridgeError::Socket(e)),
+        None => return,
+    };
+    let mut first = match first {
+        Ok(first) => first,
+        Err(e) => {
+            warn!("Dropping connection from {}: {}", addr, e);
+            return;
+        }
+    };
+    // the encoding is for zn, xi-core doesn't need to hear about it
+    let encoding = match &mut first {
+        Some(ClientMessage::ClientStarted { params }) => params.encoding.take(),
+        Some(ClientMessage::Resume { params }) => params.encoding.take(),
+        _ => None,
+    }
+    .unwrap_or_default();
+    let resume = match &first {
+        Some(ClientMessage::Resume { params }) => Some(params.session_id),
+        _ => None,
+    };
+    let (session, resumed) = match resume.and_then(|id| sessions.get(&id)) {
+        Some(session) => (session, true),
+        None => (Session::start(&sessions, config.shared), false),
+    };
+    info!(
+        "Client {} {} session {}",
+        addr,
+        if resumed { "resumed" } else { "started" },
+        session.id
+    );
+    let repr = ServerMessage::Connected {
+        session_id: session.id,
+        resumed,
+        encoding,
+    };
+    if let Err(e) = send_async(&mut ws_sink, &repr, encoding).await {
+        warn!("Dropping connection from {}: {}", addr, e);
+        return;
+    }
+
+    // Send the user's keybindings, the client falls back to its defaults without them
+    if let Some(config_dir) = config.config_dir() {
+        match keybindings::load(&config_dir) {
+            Ok(bindings) if bindings.is_empty() => {}
+            Ok(bindings) => {
+                info!(
+                    "Loaded {} keybindings from {}",
+                    bindings.len(),
+                    config_dir.display()
+                );
+                let repr = ServerMessage::Keybindings { bindings };
+                if let Err(e) = send_async(&mut ws_sink, &repr, encoding).await {
+                    warn!("Failed to send keybindings: {}", e);
+                }
+            }
+            Err(e) => warn!(
+                "Couldn't load keybindings from {}: {}",
+                config_dir.display(),
+                e
+            ),
+        }
+    }
+
+    // a client that lost its session reopens its own files instead
+    if resume.is_none() && !config.files.is_empty() {
+        let paths = config
+            .files
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        let repr = ServerMessage::OpenFiles { paths };
+        if let Err(e) = send_async(&mut ws_sink, &repr, encoding).await {
+            warn!("Failed to send files to open: {}", e);
+        }
+    }
+
+    if !resumed {
+        // a new xi-core has to hear `client_started` first
+        let started = match first {
+            Some(started @ ClientMessage::ClientStarted { .. }) => started,
+            _ => ClientMessage::ClientStarted {
+                params: ClientStartedParams {
+                    config_dir: config_dir.clone(),
+                    client_extras_dir: None,
+                    encoding: None,
+                },
+            },
+        };
+        if let Err(e) = session.to_core(started) {
+            warn!("Session {} failed to start: {}", session.id, e);
+            return;
+        }
+    }
+
+    let (attachment, messages) = session.attach();
+    let bridged = bridge(
+        &mut ws_sink,
+        ws_stream,
+        messages,
+        &session,
+        &config,
+        encoding,
+    );
+    let hangup = match bridged.await {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            warn!("Lost connection to {}: {}", addr, e);
+            Hangup::Dropped
+        }
+    };
+    // fails if the client is already gone, which is what we want anyway
+    let _ = ws_sink.close().await;
+    session.detach(Some(attachment));
+    match hangup {
+        Hangup::Closed => {
+            info!("Client {} disconnected", addr);
+            session.end(&sessions, attachment);
+        }
+        // keep the session a while, in case the client comes back
+        Hangup::Dropped => session.expire(sessions, attachment),
+        Hangup::Detached => info!("Client {} was detached from its session", addr),
+    }
+}
+
+/// How a connection ended.
+enum Hangup {
+    /// The client closed it, so it's done with its session.
+    Closed,
+    /// It dropped without a close, the client may come back to resume.
+    Dropped,
+    /// Another connection took over the session, or the session ended.
+    Detached,
+}
+
+/// Passes the client's messages on to xi-core and the session's on to the
+/// client, until the client hangs up or is detached.
+async fn bridge(
+    ws_sink: &mut WsSink,
+    ws_stream: WsStream,
+    mut messages: UnboundedReceiver<ServerMessage>,
+    session: &Session,
+    config: &Config,
+    encoding: Encoding,
+) -> Result<Hangup, BridgeError> {
+    let config_dir = config.config_dir();
+    let mut ws_stream = ws_stream.fuse();
+    loop {
+        select! {
+            msg = ws_stream.next() => {
+                let msg = match msg {
+                    Some(Ok(Message::Close(_))) => return Ok(Hangup::Closed),
+                    Some(msg) => msg.map_err(BridgeError::Socket)?,
+                    None => return Ok(Hangup::Dropped),
+                };
+                if let Some(answer) = client_to_xi(msg, session, config, &config_dir).await? {
+                    send_async(ws_sink, &answer, encoding).await?;
+                }
+            }
+            message = messages.next() => match message {
+                Some(message) => match send_async(ws_sink, &message, encoding).await {
+                    Err(e) if e.is_fatal() => return Err(e),
+                    Err(e) => warn!("{}", e),
+                    Ok(()) => {}
+                },
+                None => return Ok(Hangup::Detached),
+            },
+        }
+    }
+}
+
+/// Forwards a frame from the client to xi-core. File requests are answered
+/// here instead, off the connection's task since they touch the disk, and
+/// bad frames with a `ServerError`.
+async fn client_to_xi(
+    msg: Message,
+    session: &Session,
+    config: &Config,
+    config_dir: &Option<PathBuf>,
+) -> Result<Option<ServerMessage>, BridgeError> {
+    match client_message(msg, config_dir) {
+        Ok(Some(ClientMessage::ListDirectory { id, params })) => {
+            let workspace = config.workspace.clone();
+            let listing =
+                task::spawn_blocking(move || files::list_directory(&workspace, &params)).await;
+            Ok(Some(files::response(id, listing)))
+        }
+        Ok(Some(ClientMessage::Stat { id, params })) => {
+            let workspace = config.workspace.clone();
+            let stat = task::spawn_blocking(move || files::stat(&workspace, &params)).await;
+            Ok(Some(files::response(id, stat)))
+        }
+        Ok(Some(ClientMessage::Resume { .. })) => {
+            warn!("Ignoring `resume` from a client that is already attached");
+            Ok(None)
+        }
+        Ok(Some(js_msg)) => session.to_core(js_msg).map(|()| None),
+        Ok(None) => Ok(None),
+        Err(BridgeError::BadFrame { reason, content }) => {
+            warn!("Bad frame from client: {}", reason);
+            Ok(Some(ServerMessage::ServerError { reason, content }))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// The message in a frame from the client, if there is one.
+fn client_message(
+    msg: Message,
+    config_dir: &Option<PathBuf>,
+) -> Result<Option<ClientMessage>, BridgeError> {
+    let decoded = match msg {
+        Message::Text(text) => {
+            info!("Raw msg.to_text() = {}", text);
+            ClientMessage::from_json(&text).map_err(|e| BridgeError::BadFrame {
+                reason: e.to_string(),
+                content: text,
+            })
+        }
+        Message::Binary(bytes) => {
+            ClientMessage::from_binary(&bytes).map_err(|e| BridgeError::BadFrame {
+                reason: e.to_string(),
+                content: String::new(),
+            })
+        }
+        // tungstenite answers pings itself, and the stream ends after a close
+        _ => return Ok(None),
+    };
+    let mut js_msg = decoded?;
+    // the client can't know the server's config dir, so fill it in for xi-core
+    if let ClientMessage::ClientStarted { params } = &mut js_msg {
+        if params.config_dir.is_none() {
+            params.config_dir = config_dir.clone();
+        }
+    }
+    Ok(Some(js_msg))
+}
+
+async fn send_async(
+    ws_sink: &mut WsSink,
+    message: &ServerMessage,
+    encoding: Encoding,
+) -> Result<(), BridgeError> {
+    if log_enabled!(Level::Debug) {
+        if let Ok(pretty) = message.to_json_pretty() {
+            debug!("Sending to client: {}", pretty);
+        }
+    }
+    let frame = match encoding {
+        Encoding::Json => Message::Text(message.to_json().map_err(BridgeError::Encode)?),
+        Encoding::Cbor => Message::Binary(message.to_binary().map_err(BridgeError::EncodeBinary)?),
+    };
+    ws_sink.send(frame).await.map_err(BridgeError::Socket)
 }
@@ -3,91 +3,86 @@ extern crate web_view;
 extern crate zn;
 
 use async_std::task;
-use log::info;
+use log::{error, info};
+use std::env;
 use std::error::Error;
+use std::path::PathBuf;
+use structopt::StructOpt;
 use web_view::WVResult;
-use zn::start_websocket_server;
+use zn::{start_websocket_server, Config};
 
-fn main() -> Result<(), web_view::Error> {
-    //
-    simple_logger::init().unwrap();
+#[derive(Debug, StructOpt)]
+#[structopt(name = "zn", about = "A desktop editor on top of xi-core")]
+struct Opt {
+    /// Host to bind the editor server to
+    #[structopt(long, default_value = "127.0.0.1")]
+    host: String,
 
-    info!("Booting up");
-    // -- 0) Auto-reload for client/server in dev mode
-    // -- 0,1) Use native app instead of web tab
-    // -- 1) wire in Yew
-    // -- 2) attach WebSocketService
-    // -- 3) spawn server in separate thread w/ Socket reader
-    // -- 4) port message handling and XI-Server over to here
-    // 5a) send view message
-    // 5b) display lines
-    // 6) send edit, e.g. delete on backspace
-    let html_content = r#"<!doctype html>
-<html lang="en">
-
-<head>
-    <style>
-
-        html, body {
-            background-color: #1a1d21;
-            margin:0;
-            padding:0;
-        }
+    /// Port to bind the editor server to
+    #[structopt(short, long, default_value = "8080")]
+    port: u16,
 
-        .gutter {
-            background-color: #19171d;
-        }
+    /// Directory for xi-core preferences and zn keybindings [default: ~/.config/zn]
+    #[structopt(long, parse(from_os_str))]
+    config_dir: Option<PathBuf>,
 
-        .line {
-           font-family: monospace, monospace;
-        }
+    /// One of error, warn, info, debug or trace
+    #[structopt(long, default_value = "info")]
+    log_level: log::Level,
 
-        .line .gutter {
-            padding: 20px;
-            margin-right:10px;
-            font-size:0.8rem;
-            border-right: 1px black;
-            display: inline-block;
-            -webkit-touch-callout: none;
-            -webkit-user-select: none;
-            -khtml-user-select: none;
-            -moz-user-select: none;
-            -ms-user-select: none;
-            user-select: none;
-        }
+    /// Where to serve the client from, as built by `bin/build.sh` [default: ./pkg in the source tree]
+    #[structopt(long, parse(from_os_str))]
+    static_dir: Option<PathBuf>,
 
-        .line .code {
-            display: inline-block;
-        }
-        
-        .cursors {
-            position: absolute;
-            z-index:10;
-            color: transparent;
-        }
-        
-        .cursor {
-            display:inline-block;
-            width: 4px;
-            height: 1rem;
-            background:white;
-        }
-    </style>
-    <meta charset="utf-8" />
-    <title>Yew</title>
-    <script src="http://localhost:8085/pkg/bundle.js" defer></script>
-</head>
+    /// Only run the server, without opening a window
+    #[structopt(long)]
+    headless: bool,
 
-<body>
-</body>
+    /// Let every client edit the same buffers, showing each other's cursors
+    #[structopt(long)]
+    shared: bool,
 
-</html>"#;
+    /// Files to open at startup
+    #[structopt(parse(from_os_str))]
+    files: Vec<PathBuf>,
+}
+
+fn main() -> Result<(), web_view::Error> {
+    let opt = Opt::from_args();
+    simple_logger::init_with_level(opt.log_level).unwrap();
 
-    std::thread::spawn(|| task::block_on(start_websocket_server()));
+    let cwd = env::current_dir().unwrap_or_default();
+    let config = Config {
+        addr: format!("{}:{}", opt.host, opt.port),
+        config_dir: opt.config_dir,
+        files: opt.files.into_iter().map(|file| cwd.join(file)).collect(),
+        workspace: cwd,
+        shared: opt.shared,
+        static_dir: opt
+            .static_dir
+            .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/pkg"))),
+    };
+    let url = config.url();
+
+    info!("Booting up");
+
+    if opt.headless {
+        info!("Running headless, open {} to use the editor", url);
+        if let Err(e) = task::block_on(start_websocket_server(config)) {
+            error!("Server failed: {}", e);
+        }
+        return Ok(());
+    }
+
+    std::thread::spawn(|| {
+        if let Err(e) = task::block_on(start_websocket_server(config)) {
+            error!("Server failed: {}", e);
+        }
+    });
 
     web_view::builder()
         .title("Zinc")
-        .content(web_view::Content::Html(html_content))
+        .content(web_view::Content::Url(url))
         .size(800, 600)
         .resizable(true)
         .debug(true)
@@ -0,0 +1,1092 @@
+//! Sessions: a client's views on an xi-core, kept when the connection drops
+//! so a client that reconnects can carry on where it left off. Each session
+//! has a core of its own, unless the server is shared, where they all edit
+//! the same buffers on one core, each with views of its own.
+use async_std::task;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use zn_core::line_cache::LineCache;
+use zn_core::messages::{
+    Annotation, ClientMessage, CloseViewParams, ConfigChangedParamsChanges, Method, NewViewParams,
+    Notification, RequestId, RpcError, ServerMessage, UpdateUpdateParams, ViewId,
+};
+
+use crate::error::BridgeError;
+use crate::xi;
+
+/// How long a session waits for its client to come back before its views
+/// are closed.
+const DETACHED_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The sessions of the server, by id.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<HashMap<Uuid, Arc<Session>>>,
+    /// The core every session joins when the server is shared.
+    shared: Mutex<Weak<Core>>,
+}
+
+impl Sessions {
+    pub fn get(&self, id: &Uuid) -> Option<Arc<Session>> {
+        lock(&self.sessions).get(id).cloned()
+    }
+
+    fn insert(&self, session: Arc<Session>) {
+        lock(&self.sessions).insert(session.id, session);
+    }
+
+    fn remove(&self, id: &Uuid) -> Option<Arc<Session>> {
+        lock(&self.sessions).remove(id)
+    }
+}
+
+/// An xi-core and the sessions using it. Requests are renumbered on the way
+/// in, since every client counts its own, and xi-core's messages go to the
+/// session that opened the view they are about, or to all of them.
+pub struct Core {
+    /// Taken when the core stops, which stops xi-core.
+    xi: Mutex<Option<xi::Writer>>,
+    state: Mutex<CoreState>,
+    started_at: Instant,
+}
+
+#[derive(Default)]
+struct CoreState {
+    sessions: Vec<Arc<Session>>,
+    /// Whether xi-core has had its `client_started`, which only the first
+    /// session gets to send.
+    started: bool,
+    /// The styles and global notifications, for sessions that join later.
+    replay: Replay,
+    views: HashMap<ViewId, CoreView>,
+    /// What xi-core said about views before their `new_view` was answered.
+    unclaimed: HashMap<ViewId, Vec<ServerMessage>>,
+    /// The requests waiting on xi-core, by the id they were sent with.
+    requests: HashMap<RequestId, Forwarded>,
+    /// xi-core's `measure_width` requests, and the session measuring each.
+    measuring: HashMap<RequestId, Uuid>,
+    next_request_id: u64,
+    next_peer: u64,
+    /// Messages sent to xi-core, and received from it.
+    to_core: u64,
+    from_core: u64,
+}
+
+/// A view, and the session that opened it. Sessions opening the same file
+/// get views of their own on its buffer, so each has its own selections.
+struct CoreView {
+    session: Uuid,
+    file_path: Option<String>,
+    /// The view's selections, as of its last update.
+    selections: Vec<Vec<u64>>,
+    /// False once the buffer has changes that aren't saved.
+    pristine: bool,
+    /// Whether the session was last told of other views on the file.
+    has_presence: bool,
+}
+
+/// A request sent on to xi-core, and who to answer.
+struct Forwarded {
+    session: Uuid,
+    id: RequestId,
+    /// What a `new_view` opens, to know the view when it's answered.
+    new_view: Option<NewViewParams>,
+}
+
+impl Core {
+    /// Starts an xi-core. It runs until the last session using it leaves.
+    fn start(sessions: &Arc<Sessions>) -> Arc<Core> {
+        let (xi_write, xi_read, xi_thread) = xi::start_xi_core();
+        let core = Arc::new(Core {
+            xi: Mutex::new(Some(xi_write)),
+            state: Mutex::new(CoreState::default()),
+            started_at: Instant::now(),
+        });
+
+        let pump = Arc::clone(&core);
+        let sessions = Arc::clone(sessions);
+        task::spawn(async move {
+            pump.xi_to_sessions(xi_read).await;
+            // the channel closes as the core's thread finishes, so this is
+            // quick, but it still blocks
+            let joined = task::spawn_blocking(move || xi_thread.join()).await;
+            if joined.is_err() {
+                warn!("xi-core panicked");
+            }
+            // in case xi-core stopped on its own
+            pump.shutdown();
+
+            // xi-core stopped, so do its sessions
+            let ended = mem::take(&mut lock(&pump.state).sessions);
+            for session in ended {
+                sessions.remove(&session.id);
+                session.detach(None);
+                info!("Session {} ended", session.id);
+            }
+            let state = lock(&pump.state);
+            info!(
+                "xi-core stopped after {}s, having taken {} messages and sent {}",
+                pump.started_at.elapsed().as_secs(),
+                state.to_core,
+                state.from_core
+            );
+        });
+        core
+    }
+
+    /// Stops xi-core once it has handled what it was sent so far, so saves
+    /// on their way are still written.
+    fn shutdown(&self) {
+        if let Some(xi) = lock(&self.xi).take() {
+            // fails if xi-core is already gone
+            let _ = xi.0.unbounded_send(String::from(xi::EXIT));
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        lock(&self.xi).is_some()
+    }
+
+    fn join(self: &Arc<Self>) -> Arc<Session> {
+        let mut state = lock(&self.state);
+        let session = Arc::new(Session {
+            id: Uuid::new_v4(),
+            peer: state.next_peer,
+            core: Arc::clone(self),
+            inner: Mutex::new(Inner {
+                client: None,
+                attachment: 0,
+                replay: state.replay.globals(),
+            }),
+        });
+        state.next_peer += 1;
+        state.sessions.push(Arc::clone(&session));
+        session
+    }
+
+    /// Closes the views of `session`, and stops xi-core if no other session
+    /// is using it.
+    fn leave(&self, session: &Session) {
+        let (views, unmeasured, last) = {
+            let mut state = lock(&self.state);
+            state.sessions.retain(|other| other.id != session.id);
+            state.abandon_requests(session.id);
+            let unmeasured = state.abandon_measurements(session.id);
+            let views: Vec<ViewId> = state
+                .views
+                .iter()
+                .filter(|(_, view)| view.session == session.id)
+                .map(|(view_id, _)| view_id.clone())
+                .collect();
+            for view_id in &views {
+                let view = &state.views[view_id];
+                if !view.pristine {
+                    let name = view.file_path.as_deref().unwrap_or("an untitled view");
+                    warn!("Closing {} with unsaved changes", name);
+                }
+                state.close_view(view_id);
+            }
+            (views, unmeasured, state.sessions.is_empty())
+        };
+        if last {
+            self.shutdown();
+            return;
+        }
+        for id in unmeasured {
+            self.refuse_measurement(id);
+        }
+        for view_id in views {
+            self.close_in_xi(view_id);
+        }
+    }
+
+    /// Tells xi-core to close `view_id`.
+    fn close_in_xi(&self, view_id: ViewId) {
+        let close = ClientMessage::CloseView {
+            params: CloseViewParams { view_id },
+        };
+        if let Err(e) = self.send_to_xi(&close) {
+            warn!("{}", e);
+        }
+    }
+
+    /// Sends a message from `session` on to xi-core.
+    fn forward(&self, session: &Session, message: ClientMessage) -> Result<(), BridgeError> {
+        let mut state = lock(&self.state);
+        state.to_core += 1;
+        let message = match message {
+            ClientMessage::ClientStarted { .. } if state.started => return Ok(()),
+            ClientMessage::ClientStarted { params } => {
+                state.started = true;
+                ClientMessage::ClientStarted { params }
+            }
+            ClientMessage::NewView { id, params } => ClientMessage::NewView {
+                id: state.forward_request(session.id, id, Some(params.clone())),
+                params,
+            },
+            ClientMessage::GetConfig { id, params } => ClientMessage::GetConfig {
+                id: state.forward_request(session.id, id, None),
+                params,
+            },
+            ClientMessage::Edit {
+                id: Some(id),
+                params,
+            } => ClientMessage::Edit {
+                id: Some(state.forward_request(session.id, id, None)),
+                params,
+            },
+            ClientMessage::CloseView { params } => {
+                state.close_view(&params.view_id);
+                ClientMessage::CloseView { params }
+            }
+            ClientMessage::Save { params } => {
+                if let Some(view) = state.views.get_mut(&params.view_id) {
+                    view.file_path = Some(params.file_path.clone());
+                }
+                ClientMessage::Save { params }
+            }
+            ClientMessage::MeasuredWidths { id, widths } => {
+                if state.measuring.remove(&id).is_none() {
+                    warn!("Dropping widths for unknown request {:?}", id);
+                    return Ok(());
+                }
+                let result = serde_json::to_value(widths).map_err(BridgeError::Encode)?;
+                return self.answer(&Notification::Result { id, result });
+            }
+            message => message,
+        };
+        self.send_to_xi(&message)
+    }
+
+    fn send_to_xi(&self, message: &ClientMessage) -> Result<(), BridgeError> {
+        let json = message.to_json().map_err(BridgeError::Encode)?;
+        self.send_json_to_xi(json)
+    }
+
+    /// Answers a request xi-core made.
+    fn answer(&self, answer: &Notification) -> Result<(), BridgeError> {
+        let json = serde_json::to_string(answer).map_err(BridgeError::Encode)?;
+        self.send_json_to_xi(json)
+    }
+
+    /// Answers a `measure_width` with an error, so xi-core doesn't wait on
+    /// widths that aren't coming.
+    fn refuse_measurement(&self, id: RequestId) {
+        let error = RpcError {
+            code: 0,
+            message: String::from("No client to measure text"),
+            data: None,
+        };
+        if let Err(e) = self.answer(&Notification::Error { id, error }) {
+            warn!("{}", e);
+        }
+    }
+
+    fn send_json_to_xi(&self, json: String) -> Result<(), BridgeError> {
+        info!("Sending message to XI: {}", json);
+        match &*lock(&self.xi) {
+            Some(xi) => {
+                xi.0.unbounded_send(json)
+                    .map_err(|_| BridgeError::CoreClosed)
+            }
+            None => Err(BridgeError::CoreClosed),
+        }
+    }
+
+    /// Hands xi-core's messages to the sessions until xi-core stops.
+    async fn xi_to_sessions(&self, mut xi: xi::Reader) {
+        // the channel only closes once xi-core has stopped
+        while let Some(msg) = xi.0.next().await {
+            info!("Sending message to client {}", msg);
+            let repr = match ServerMessage::from_xi_json(&msg) {
+                Ok(repr) => repr,
+                Err(e) => {
+                    warn!("{}", BridgeError::BadCoreMessage(e));
+                    continue;
+                }
+            };
+            match &repr {
+                ServerMessage::EditorMethod(Method::Unknown { method, .. }) => {
+                    warn!("Passing on unknown method `{}` from xi-core", method)
+                }
+                ServerMessage::Unrecognized(_) => {
+                    warn!("Passing on a message zn doesn't understand: {}", msg)
+                }
+                _ => {}
+            }
+            self.receive(repr);
+        }
+    }
+
+    /// Hands a message from xi-core to the sessions it's for.
+    fn receive(&self, message: ServerMessage) {
+        let mut state = lock(&self.state);
+        state.from_core += 1;
+        if let ServerMessage::EditorMethod(Method::MeasureWidth { id, .. }) = &message {
+            let id = *id;
+            if !state.measure(message) {
+                drop(state);
+                self.refuse_measurement(id);
+            }
+            return;
+        }
+        if let Some(view_id) = state.route(message) {
+            drop(state);
+            self.close_in_xi(view_id);
+        }
+    }
+}
+
+impl CoreState {
+    /// Gives `id` a number unique on this core, to send it to xi-core with.
+    fn forward_request(
+        &mut self,
+        session: Uuid,
+        id: RequestId,
+        new_view: Option<NewViewParams>,
+    ) -> RequestId {
+        let core_id = RequestId(self.next_request_id);
+        self.next_request_id += 1;
+        self.requests.insert(
+            core_id,
+            Forwarded {
+                session,
+                id,
+                new_view,
+            },
+        );
+        core_id
+    }
+
+    /// Forgets the requests of `session`, but for its `new_view`s: the views
+    /// xi-core opens for them are closed as they come.
+    fn abandon_requests(&mut self, session: Uuid) {
+        self.requests
+            .retain(|_, forwarded| forwarded.session != session || forwarded.new_view.is_some());
+    }
+
+    /// Returns a view xi-core opened for a session that has since left, to
+    /// close it.
+    fn route(&mut self, message: ServerMessage) -> Option<ViewId> {
+        match message {
+            ServerMessage::EditorNotification(Notification::Result { id, result }) => {
+                let forwarded = match self.requests.remove(&id) {
+                    Some(forwarded) => forwarded,
+                    None => {
+                        warn!("Dropping xi-core's answer to unknown request {:?}", id);
+                        return None;
+                    }
+                };
+                let view_id = match &forwarded.new_view {
+                    Some(_) => serde_json::from_value::<ViewId>(result.clone()).ok(),
+                    None => None,
+                };
+                let left = !self
+                    .sessions
+                    .iter()
+                    .any(|other| other.id == forwarded.session);
+                if left {
+                    if let Some(view_id) = &view_id {
+                        self.unclaimed.remove(view_id);
+                    }
+                    return view_id;
+                }
+                let answer = Notification::Result {
+                    id: forwarded.id,
+                    result,
+                };
+                self.deliver(
+                    forwarded.session,
+                    &ServerMessage::EditorNotification(answer),
+                );
+                if let (Some(view_id), Some(params)) = (view_id, forwarded.new_view) {
+                    self.claim(view_id, forwarded.session, params.file_path);
+                }
+            }
+            ServerMessage::EditorNotification(Notification::Error { id, error }) => {
+                match self.requests.remove(&id) {
+                    Some(forwarded) => {
+                        let answer = Notification::Error {
+                            id: forwarded.id,
+                            error,
+                        };
+                        self.deliver(
+                            forwarded.session,
+                            &ServerMessage::EditorNotification(answer),
+                        );
+                    }
+                    None => warn!("Dropping xi-core's error for unknown request {:?}", id),
+                }
+            }
+            ServerMessage::EditorMethod(method) => match method.view_id().cloned() {
+                Some(view_id) => {
+                    let session = match self.views.get(&view_id) {
+                        Some(view) => view.session,
+                        None => {
+                            let unclaimed = self.unclaimed.entry(view_id).or_default();
+                            unclaimed.push(ServerMessage::EditorMethod(method));
+                            return None;
+                        }
+                    };
+                    if let Method::Update { update, .. } = &method {
+                        if let Some(view) = self.views.get_mut(&view_id) {
+                            view.pristine = update.pristine;
+                        }
+                        self.update_selections(&view_id, &update.annotations);
+                    }
+                    self.deliver(session, &ServerMessage::EditorMethod(method));
+                }
+                None => {
+                    let message = ServerMessage::EditorMethod(method);
+                    self.replay.record(&message);
+                    self.deliver_all(&message);
+                }
+            },
+            message => self.deliver_all(&message),
+        }
+        None
+    }
+
+    /// Hands a `measure_width` to a session with a client to measure it.
+    /// Returns false if there's none.
+    fn measure(&mut self, message: ServerMessage) -> bool {
+        let id = match &message {
+            ServerMessage::EditorMethod(Method::MeasureWidth { id, .. }) => *id,
+            _ => return false,
+        };
+        match self.sessions.iter().find(|session| session.is_attached()) {
+            Some(session) => {
+                self.measuring.insert(id, session.id);
+                session.deliver(&message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forgets the measurements `session` was asked for, returning them.
+    fn abandon_measurements(&mut self, session: Uuid) -> Vec<RequestId> {
+        let ids: Vec<RequestId> = self
+            .measuring
+            .iter()
+            .filter(|(_, measurer)| **measurer == session)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            self.measuring.remove(id);
+        }
+        ids
+    }
+
+    fn deliver(&self, session: Uuid, message: &ServerMessage) {
+        if let Some(session) = self.sessions.iter().find(|other| other.id == session) {
+            session.deliver(message);
+        }
+    }
+
+    fn deliver_all(&self, message: &ServerMessage) {
+        for session in &self.sessions {
+            session.deliver(message);
+        }
+    }
+
+    /// Gives `view_id` to `session`, along with whatever xi-core already
+    /// said about it.
+    fn claim(&mut self, view_id: ViewId, session: Uuid, file_path: Option<String>) {
+        self.views.insert(
+            view_id.clone(),
+            CoreView {
+                session,
+                file_path,
+                selections: Vec::new(),
+                pristine: true,
+                has_presence: false,
+            },
+        );
+        for message in self.unclaimed.remove(&view_id).unwrap_or_default() {
+            self.route(message);
+        }
+        self.update_presence(&view_id);
+    }
+
+    fn close_view(&mut self, view_id: &ViewId) {
+        self.unclaimed.remove(view_id);
+        if let Some(view) = self.views.remove(view_id) {
+            if let Some(file_path) = view.file_path {
+                self.update_presence_on(&file_path);
+            }
+        }
+    }
+
+    fn update_selections(&mut self, view_id: &ViewId, annotations: &[Annotation]) {
+        let selections = annotations
+            .iter()
+            .find_map(|annotation| match annotation {
+                Annotation::Selection { ranges, .. } => Some(ranges.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        match self.views.get_mut(view_id) {
+            Some(view) if view.selections != selections => view.selections = selections,
+            _ => return,
+        }
+        self.update_presence(view_id);
+    }
+
+    /// Tells the sessions with views on `view_id`'s file where the
+    /// selections of the other views are.
+    fn update_presence(&mut self, view_id: &ViewId) {
+        let file_path = self
+            .views
+            .get(view_id)
+            .and_then(|view| view.file_path.clone());
+        if let Some(file_path) = file_path {
+            self.update_presence_on(&file_path);
+        }
+    }
+
+    fn update_presence_on(&mut self, file_path: &str) {
+        let on_file: Vec<ViewId> = self
+            .views
+            .iter()
+            .filter(|(_, view)| view.file_path.as_deref() == Some(file_path))
+            .map(|(view_id, _)| view_id.clone())
+            .collect();
+        for view_id in on_file {
+            let annotations: Vec<Annotation> = self
+                .views
+                .iter()
+                .filter(|(other_id, other)| {
+                    **other_id != view_id
+                        && other.file_path.as_deref() == Some(file_path)
+                        && !other.selections.is_empty()
+                })
+                .map(|(_, other)| Annotation::Presence {
+                    peer: self.peer(other.session),
+                    n: other.selections.len() as u64,
+                    ranges: other.selections.clone(),
+                })
+                .collect();
+            let view = self.views.get_mut(&view_id).expect("listed above");
+            // only sessions sharing a file hear about presence at all
+            if annotations.is_empty() && !view.has_presence {
+                continue;
+            }
+            view.has_presence = !annotations.is_empty();
+            let session = view.session;
+            let presence = ServerMessage::Presence {
+                view_id,
+                annotations,
+            };
+            self.deliver(session, &presence);
+        }
+    }
+
+    fn peer(&self, session: Uuid) -> u64 {
+        self.sessions
+            .iter()
+            .find(|other| other.id == session)
+            .map_or(0, |other| other.peer)
+    }
+}
+
+pub struct Session {
+    pub id: Uuid,
+    /// Tells the session apart from the others on its core.
+    peer: u64,
+    core: Arc<Core>,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// Where messages for the attached client go, to its connection.
+    client: Option<UnboundedSender<ServerMessage>>,
+    /// Goes up with every attach, so a connection that was taken over
+    /// doesn't detach the one that took over.
+    attachment: u64,
+    replay: Replay,
+}
+
+impl Session {
+    /// Starts a session on a core of its own, or on the shared one if
+    /// `shared`, whose messages go to whichever client is attached.
+    pub fn start(sessions: &Arc<Sessions>, shared: bool) -> Arc<Session> {
+        let core = if shared {
+            let mut slot = lock(&sessions.shared);
+            match slot.upgrade().filter(|core| core.is_running()) {
+                Some(core) => core,
+                None => {
+                    let core = Core::start(sessions);
+                    *slot = Arc::downgrade(&core);
+                    core
+                }
+            }
+        } else {
+            Core::start(sessions)
+        };
+        let session = core.join();
+        sessions.insert(Arc::clone(&session));
+        info!("Started session {}", session.id);
+        session
+    }
+
+    /// Attaches a new client in place of any other, and catches it up on
+    /// everything xi-core has told the session so far. Returns the
+    /// attachment for `detach`, and the messages for the client, which end
+    /// when it is detached.
+    pub fn attach(&self) -> (u64, UnboundedReceiver<ServerMessage>) {
+        let (client, messages) = unbounded();
+        let mut inner = lock(&self.inner);
+        for message in inner.replay.messages() {
+            // can't fail, the receiver is right here
+            let _ = client.unbounded_send(message);
+        }
+        if inner.client.replace(client).is_some() {
+            warn!("Session {} was taken over by a new connection", self.id);
+        }
+        inner.attachment += 1;
+        (inner.attachment, messages)
+    }
+
+    /// Detaches the client, if it's still `attachment`, or whichever it is
+    /// if `None`. Its connection closes once it has sent what it was given.
+    pub fn detach(&self, attachment: Option<u64>) {
+        {
+            let mut inner = lock(&self.inner);
+            if attachment.is_some() && attachment != Some(inner.attachment) {
+                return;
+            }
+            inner.client = None;
+        }
+        // the widths the client was asked for won't come now
+        let unmeasured = lock(&self.core.state).abandon_measurements(self.id);
+        for id in unmeasured {
+            self.core.refuse_measurement(id);
+        }
+    }
+
+    fn is_attached(&self) -> bool {
+        lock(&self.inner).client.is_some()
+    }
+
+    /// Stops the session after `DETACHED_TIMEOUT`, unless a client attached
+    /// again since `attachment` ended.
+    pub fn expire(self: Arc<Self>, sessions: Arc<Sessions>, attachment: u64) {
+        task::spawn(async move {
+            task::sleep(DETACHED_TIMEOUT).await;
+            if self.end(&sessions, attachment) {
+                info!("Session {} expired", self.id);
+            }
+        });
+    }
+
+    /// Stops the session now, unless a client attached again since
+    /// `attachment` ended. Returns whether it did.
+    pub fn end(&self, sessions: &Sessions, attachment: u64) -> bool {
+        let idle = {
+            let inner = lock(&self.inner);
+            inner.attachment == attachment && inner.client.is_none()
+        };
+        if idle {
+            sessions.remove(&self.id);
+            self.stop();
+        }
+        idle
+    }
+
+    /// Closes the session's views, stopping xi-core if it was the last
+    /// session using it.
+    pub fn stop(&self) {
+        self.core.leave(self);
+    }
+
+    pub fn to_core(&self, message: ClientMessage) -> Result<(), BridgeError> {
+        if let ClientMessage::CloseView { params } = &message {
+            lock(&self.inner).replay.views.remove(&params.view_id);
+        }
+        self.core.forward(self, message)
+    }
+
+    /// Passes on a message from xi-core. While no client is attached it is
+    /// only kept for the replay.
+    fn deliver(&self, message: &ServerMessage) {
+        let mut inner = lock(&self.inner);
+        inner.replay.record(message);
+        let sent = match &inner.client {
+            Some(client) => client.unbounded_send(message.clone()),
+            None => Ok(()),
+        };
+        if sent.is_err() {
+            // the connection ended, and detaches itself too
+            warn!("Lost the client of session {}", self.id);
+            inner.client = None;
+        }
+    }
+}
+
+/// What a client needs to be told again when it attaches: the styles and
+/// themes, and each view's settings and lines.
+#[derive(Default)]
+struct Replay {
+    styles: Vec<Method>,
+    /// The last of each kind of global notification.
+    globals: Vec<Method>,
+    views: BTreeMap<ViewId, ViewReplay>,
+}
+
+#[derive(Default)]
+struct ViewReplay {
+    config: ConfigChangedParamsChanges,
+    lines: LineCache,
+    annotations: Vec<Annotation>,
+    pristine: bool,
+    /// Where the other clients on the file are.
+    presence: Vec<Annotation>,
+    /// The last of each kind of other notification about the view.
+    others: Vec<Method>,
+}
+
+/// Adds `method` to `methods`, replacing any of the same kind.
+fn keep_last(methods: &mut Vec<Method>, method: &Method) {
+    methods.retain(|kept| mem::discriminant(kept) != mem::discriminant(method));
+    methods.push(method.clone());
+}
+
+impl Replay {
+    /// A replay with only the styles and global notifications of this one.
+    fn globals(&self) -> Replay {
+        Replay {
+            styles: self.styles.clone(),
+            globals: self.globals.clone(),
+            views: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, message: &ServerMessage) {
+        let method = match message {
+            ServerMessage::EditorMethod(method) => method,
+            ServerMessage::Presence {
+                view_id,
+                annotations,
+            } => {
+                let view = self.views.entry(view_id.clone()).or_default();
+                view.presence = annotations.clone();
+                return;
+            }
+            _ => return,
+        };
+        match method {
+            Method::DefStyle(_) => self.styles.push(method.clone()),
+            Method::AvailableThemes { .. }
+            | Method::AvailableLanguages { .. }
+            | Method::ThemeChanged { .. } => keep_last(&mut self.globals, method),
+            Method::Update { update, view_id } => {
+                let view = self.views.entry(view_id.clone()).or_default();
+                view.lines.apply(update.ops.clone());
+                view.annotations = update.annotations.clone();
+                view.pristine = update.pristine;
+            }
+            Method::ConfigChanged { changes, view_id } => {
+                let view = self.views.entry(view_id.clone()).or_default();
+                view.config.merge(changes.clone());
+            }
+            Method::AvailablePlugins { view_id, .. } | Method::LanguageChanged { view_id, .. } => {
+                let view = self.views.entry(view_id.clone()).or_default();
+                keep_last(&mut view.others, method);
+            }
+            _ => {}
+        }
+    }
+
+    fn messages(&self) -> Vec<ServerMessage> {
+        let mut messages: Vec<ServerMessage> = self
+            .styles
+            .iter()
+            .chain(&self.globals)
+            .cloned()
+            .map(ServerMessage::EditorMethod)
+            .collect();
+        for (view_id, view) in &self.views {
+            let mut methods = vec![Method::ConfigChanged {
+                changes: view.config.clone(),
+                view_id: view_id.clone(),
+            }];
+            methods.extend(view.others.iter().cloned());
+            // replaces whatever lines the client has, it may have missed updates
+            methods.push(Method::Update {
+                update: UpdateUpdateParams {
+                    annotations: view.annotations.clone(),
+                    ops: view.lines.to_ops(),
+                    pristine: view.pristine,
+                },
+                view_id: view_id.clone(),
+            });
+            messages.extend(methods.into_iter().map(ServerMessage::EditorMethod));
+            if !view.presence.is_empty() {
+                messages.push(ServerMessage::Presence {
+                    view_id: view_id.clone(),
+                    annotations: view.presence.clone(),
+                });
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use zn_core::messages::Request;
+
+    /// A core whose xi-core is a channel, so the test can play xi-core.
+    fn core() -> (Arc<Core>, UnboundedReceiver<String>) {
+        let (to_xi, from_core) = unbounded();
+        let core = Arc::new(Core {
+            xi: Mutex::new(Some(xi::Writer(to_xi))),
+            state: Mutex::new(CoreState::default()),
+            started_at: Instant::now(),
+        });
+        (core, from_core)
+    }
+
+    /// A session on `core` with a client attached, and what it's sent.
+    fn client(core: &Arc<Core>) -> (Arc<Session>, UnboundedReceiver<ServerMessage>) {
+        let session = core.join();
+        let (_, messages) = session.attach();
+        (session, messages)
+    }
+
+    /// Everything sent since the last call.
+    fn received<T>(messages: &mut UnboundedReceiver<T>) -> Vec<T> {
+        let mut received = Vec::new();
+        while let Ok(Some(message)) = messages.try_next() {
+            received.push(message);
+        }
+        received
+    }
+
+    /// The JSON-RPC ids of what xi-core was sent since the last call.
+    fn sent_ids(to_xi: &mut UnboundedReceiver<String>) -> Vec<Value> {
+        received(to_xi)
+            .iter()
+            .map(|json| serde_json::from_str::<Value>(json).unwrap()["id"].clone())
+            .collect()
+    }
+
+    fn from_xi(core: &Core, json: Value) {
+        core.receive(ServerMessage::from_xi_json(&json.to_string()).unwrap());
+    }
+
+    fn open(session: &Session, id: u64, file_path: &str) {
+        let new_view = ClientMessage::NewView {
+            id: RequestId(id),
+            params: NewViewParams {
+                file_path: Some(String::from(file_path)),
+            },
+        };
+        session.to_core(new_view).unwrap();
+    }
+
+    fn update(view_id: &str, text: &str) -> Value {
+        json!({"method": "update", "params": {"view_id": view_id, "update": {
+            "annotations": [],
+            "ops": [{"op": "ins", "n": 1, "lines": [{"cursor": null, "text": text}]}],
+            "pristine": true,
+        }}})
+    }
+
+    /// The id and result of an answer to a request.
+    fn answer(message: &ServerMessage) -> (RequestId, Value) {
+        match message {
+            ServerMessage::EditorNotification(Notification::Result { id, result }) => {
+                (*id, result.clone())
+            }
+            other => panic!("not an answer: {:?}", other),
+        }
+    }
+
+    /// The text of the lines an update inserts.
+    fn inserted(message: &ServerMessage) -> Vec<String> {
+        match message {
+            ServerMessage::EditorMethod(Method::Update { update, .. }) => update
+                .ops
+                .iter()
+                .flat_map(|op| op.lines.iter().flatten())
+                .map(|line| line.text.clone())
+                .collect(),
+            other => panic!("not an update: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn requests_are_renumbered_and_answered_to_their_session() {
+        let (core, mut to_xi) = core();
+        let (a, mut a_messages) = client(&core);
+        let (b, mut b_messages) = client(&core);
+        open(&a, 0, "a.rs");
+        open(&b, 0, "b.rs");
+        assert_eq!(sent_ids(&mut to_xi), vec![json!(0), json!(1)]);
+
+        from_xi(&core, json!({"id": 1, "result": "view-id-2"}));
+        assert!(received(&mut a_messages).is_empty());
+        let b_answers = received(&mut b_messages);
+        assert_eq!(answer(&b_answers[0]), (RequestId(0), json!("view-id-2")));
+
+        from_xi(&core, json!({"id": 0, "result": "view-id-1"}));
+        let a_answers = received(&mut a_messages);
+        assert_eq!(answer(&a_answers[0]), (RequestId(0), json!("view-id-1")));
+
+        // answered requests are forgotten
+        from_xi(&core, json!({"id": 0, "result": "view-id-3"}));
+        assert!(received(&mut a_messages).is_empty());
+    }
+
+    #[test]
+    fn edit_requests_are_renumbered_too() {
+        let (core, mut to_xi) = core();
+        let (a, mut messages) = client(&core);
+        open(&a, 0, "a.rs");
+        let copy = Request::Copy(String::from("view-id-1")).into_message(RequestId(9));
+        a.to_core(copy).unwrap();
+        assert_eq!(sent_ids(&mut to_xi), vec![json!(0), json!(1)]);
+
+        from_xi(&core, json!({"id": 1, "result": "fn main"}));
+        let answers = received(&mut messages);
+        assert_eq!(answer(&answers[0]), (RequestId(9), json!("fn main")));
+    }
+
+    #[test]
+    fn route_sends_view_methods_to_their_session_and_the_rest_to_all() {
+        let (core, _to_xi) = core();
+        let (a, mut a_messages) = client(&core);
+        let (b, mut b_messages) = client(&core);
+        open(&a, 0, "a.rs");
+        open(&b, 0, "b.rs");
+        from_xi(&core, json!({"id": 0, "result": "view-id-1"}));
+        from_xi(&core, json!({"id": 1, "result": "view-id-2"}));
+        received(&mut a_messages);
+        received(&mut b_messages);
+
+        from_xi(&core, update("view-id-1", "a\n"));
+        assert_eq!(inserted(&received(&mut a_messages)[0]), vec!["a\n"]);
+        assert!(received(&mut b_messages).is_empty());
+
+        from_xi(
+            &core,
+            json!({"method": "available_themes", "params": {"themes": []}}),
+        );
+        assert_eq!(received(&mut a_messages).len(), 1);
+        assert_eq!(received(&mut b_messages).len(), 1);
+    }
+
+    #[test]
+    fn claim_delivers_what_came_before_the_answer() {
+        let (core, _to_xi) = core();
+        let (a, mut messages) = client(&core);
+        open(&a, 4, "a.rs");
+        from_xi(&core, update("view-id-1", "early\n"));
+        assert!(received(&mut messages).is_empty());
+
+        from_xi(&core, json!({"id": 0, "result": "view-id-1"}));
+        let messages = received(&mut messages);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(answer(&messages[0]), (RequestId(4), json!("view-id-1")));
+        assert_eq!(inserted(&messages[1]), vec!["early\n"]);
+    }
+
+    #[test]
+    fn opening_an_open_file_gets_a_view_of_its_own() {
+        let (core, mut to_xi) = core();
+        let (a, mut a_messages) = client(&core);
+        let (b, mut b_messages) = client(&core);
+        open(&a, 0, "a.rs");
+        from_xi(&core, json!({"id": 0, "result": "view-id-1"}));
+        open(&b, 0, "a.rs");
+        assert_eq!(sent_ids(&mut to_xi), vec![json!(0), json!(1)]);
+        from_xi(&core, json!({"id": 1, "result": "view-id-2"}));
+        received(&mut a_messages);
+        received(&mut b_messages);
+
+        // xi-core updates each view of the buffer, and each goes to its session
+        from_xi(&core, update("view-id-1", "x\n"));
+        from_xi(&core, update("view-id-2", "x\n"));
+        assert_eq!(received(&mut a_messages).len(), 1);
+        assert_eq!(received(&mut b_messages).len(), 1);
+    }
+
+    #[test]
+    fn a_view_opened_for_a_session_that_left_is_closed() {
+        let (core, mut to_xi) = core();
+        let (a, _a_messages) = client(&core);
+        let (_b, _b_messages) = client(&core);
+        open(&a, 3, "a.rs");
+        a.stop();
+        received(&mut to_xi);
+
+        from_xi(&core, update("view-id-1", "early\n"));
+        from_xi(&core, json!({"id": 0, "result": "view-id-1"}));
+        let sent = received(&mut to_xi);
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("close_view"));
+        assert!(sent[0].contains("view-id-1"));
+        let state = lock(&core.state);
+        assert!(state.views.is_empty());
+        assert!(state.unclaimed.is_empty());
+        assert!(state.requests.is_empty());
+    }
+
+    #[test]
+    fn update_presence_on_tells_other_views_on_the_file() {
+        let (core, _to_xi) = core();
+        let (a, mut a_messages) = client(&core);
+        let (b, mut b_messages) = client(&core);
+        let mut state = lock(&core.state);
+        let file = Some(String::from("a.rs"));
+        state.claim(String::from("view-id-1"), a.id, file.clone());
+        state.claim(String::from("view-id-2"), b.id, file);
+        received(&mut a_messages);
+        received(&mut b_messages);
+
+        let ranges = vec![vec![0, 0, 0, 2]];
+        state.views.get_mut("view-id-1").unwrap().selections = ranges.clone();
+        state.update_presence_on("a.rs");
+        // only the other view has anyone else's selections to show
+        assert!(received(&mut a_messages).is_empty());
+        match &received(&mut b_messages)[..] {
+            [ServerMessage::Presence {
+                view_id,
+                annotations,
+            }] => {
+                assert_eq!(view_id, "view-id-2");
+                match &annotations[..] {
+                    [Annotation::Presence { peer, n, ranges: r }] => {
+                        assert_eq!((*peer, *n, r), (a.peer, 1, &ranges));
+                    }
+                    other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // clearing them is said once, and then nothing more
+        state.views.get_mut("view-id-1").unwrap().selections = Vec::new();
+        state.update_presence_on("a.rs");
+        match &received(&mut b_messages)[..] {
+            [ServerMessage::Presence { annotations, .. }] => assert!(annotations.is_empty()),
+            other => panic!("{:?}", other),
+        }
+        state.update_presence_on("a.rs");
+        assert!(received(&mut b_messages).is_empty());
+    }
+}
@@ -1,30 +1,30 @@
-use uuid::Uuid;
-use zn_core::messages::{ClientMessage, ServerMessage};
+use async_std::task;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use std::io::{self, Write};
+use std::thread::{self, JoinHandle};
 
-use std::io::{self, BufRead, Read, Write};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-
-use serde_json::json;
-use serde_json::Value;
-use std::sync::{Arc, Mutex};
 use xi_core_lib::XiCore;
-use xi_rpc::RpcLoop;
+use xi_rpc::{ReadError, RpcLoop};
+
+use log::{info, trace, warn};
 
-use log::info;
+/// Tells the core's loop to stop, as if its input had ended. Anything sent
+/// before it is still handled, saves included.
+pub const EXIT: &str = r#"{"method":"command","params":{"method":"exit"}}"#;
 
-/// Wraps an instance of `mpsc::Sender`, implementing `Write`.
+/// Wraps an instance of `UnboundedSender`, implementing `Write`.
 ///
-/// This lets the tx side of an mpsc::channel serve as the destination
-/// stream for an RPC loop.
+/// This lets the tx side of a channel serve as the destination stream for
+/// an RPC loop. Sending never blocks, so async code can use it too.
 #[derive(Debug)]
-pub struct Writer(pub Sender<String>);
+pub struct Writer(pub UnboundedSender<String>);
 
 impl Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let s = String::from_utf8(buf.to_vec()).unwrap();
         self.0
-            .send(s)
+            .unbounded_send(s)
             .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{:?}", err)))
             .map(|_| buf.len())
     }
@@ -34,12 +34,13 @@ impl Write for Writer {
     }
 }
 
-/// Wraps an instance of `mpsc::Receiver`, providing convenience methods
-/// for parsing received messages.
+/// Wraps an instance of `UnboundedReceiver`, providing convenience methods
+/// for parsing received messages. It is a stream for async code, and blocks
+/// in `read_line` for xi-rpc's loop.
 #[derive(Debug)]
-pub struct Reader(pub Receiver<String>);
+pub struct Reader(pub UnboundedReceiver<String>);
 
-impl Read for Reader {
+impl io::Read for Reader {
     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
         unreachable!("didn't expect xi-rpc to call read");
     }
@@ -47,7 +48,7 @@ impl Read for Reader {
 
 // Note: we don't properly implement BufRead, only the stylized call patterns
 // used by xi-rpc.
-impl BufRead for Reader {
+impl io::BufRead for Reader {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         unreachable!("didn't expect xi-rpc to call fill_buf");
     }
@@ -57,20 +58,20 @@ impl BufRead for Reader {
     }
 
     fn read_line(&mut self, buf: &mut String) -> io::Result<usize> {
-        info!("Reader received {:?}", buf);
+        trace!("Reader received {:?}", buf);
 
-        let event = match self.0.recv() {
-            Ok(s) => {
-                info!("Reader received {:?}", s);
+        let event = match task::block_on(self.0.next()) {
+            Some(s) => {
+                trace!("Reader received {:?}", s);
                 s
             }
-            Err(e) => {
-                info!("Reader has RecvError, {:?}", e);
+            None => {
+                info!("Reader's channel closed");
                 return Ok(0);
             }
         };
 
-        if &event == r#"{"method":"command","params":{"method":"exit"}}"# {
+        if event == EXIT {
             // It receive a close commmand from the writer indicating the chan
             // should be closes. The event is sent by the InputController when
             // the user ask to quit the program.
@@ -95,56 +96,31 @@ impl BufRead for Reader {
     }
 }
 
-pub struct ClientToClientWriter(Writer);
-
-impl ClientToClientWriter {
-    pub fn send_rpc_notification(&mut self, method: &str, params: &Value) {
-        let raw_content = match serde_json::to_vec(&json!({"method": method, "params": params})) {
-            Ok(raw) => raw,
-            Err(err) => {
-                //slog::error!(self.log, "failed to create the notification {}: {}", method, err);
-                return;
-            }
-        };
-
-        match self.0.write(&raw_content) {
-            Ok(_) => (),
-            Err(err) => (), //slog::error!(self.log, "failed to send the notification {}: {}", method, err),
-        };
-    }
-}
-
 // core will write to core_to_client_writer,
 // i.e. core_to_client_reader will receive messages that can be
 // sent over the wire to the client
 // messages coming from the client must be written to client_to_core_writer
-pub fn start_xi_core() -> (Writer, Reader, ClientToClientWriter) {
+// the core runs on its own thread, which ends once its input does
+pub fn start_xi_core() -> (Writer, Reader, JoinHandle<()>) {
     let mut core = XiCore::new();
 
-    let (to_core_tx, to_core_rx) = channel();
+    let (to_core_tx, to_core_rx) = unbounded();
     let client_to_core_writer = Writer(to_core_tx);
     let client_to_core_reader = Reader(to_core_rx);
 
-    let (from_core_tx, from_core_rx) = channel();
-    let core_to_client_writer = Writer(from_core_tx.clone());
+    let (from_core_tx, from_core_rx) = unbounded();
+    let core_to_client_writer = Writer(from_core_tx);
     let core_to_client_reader = Reader(from_core_rx);
 
-    let client_to_client_writer = ClientToClientWriter(Writer(from_core_tx));
     info!("Running core event loop!");
     let mut core_event_loop = RpcLoop::new(core_to_client_writer);
-    thread::spawn(move || {
-        core_event_loop
-            .mainloop(|| client_to_core_reader, &mut core)
-            .map_err(|e| {
-                info!("mainloop failed with {:?}", e);
-                e
-            })
-            .expect("core_event_loop.mainloop return OK")
+    let core_thread = thread::spawn(move || {
+        match core_event_loop.mainloop(|| client_to_core_reader, &mut core) {
+            // `EXIT` ends the input, as does the channel closing
+            Ok(()) | Err(ReadError::Disconnect) => info!("Core event loop stopped"),
+            Err(e) => warn!("Core event loop failed: {:?}", e),
+        }
     });
 
-    (
-        client_to_core_writer,
-        core_to_client_reader,
-        client_to_client_writer,
-    )
+    (client_to_core_writer, core_to_client_reader, core_thread)
 }
@@ -0,0 +1,104 @@
+//! Clients that connect and close again shouldn't leave xi-core threads
+//! behind.
+use async_std::net::TcpStream;
+use async_std::task;
+use futures::{SinkExt, StreamExt};
+use std::fs;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tungstenite::Message;
+use url::Url;
+use zn::{start_websocket_server, Config};
+
+const CYCLES: usize = 10;
+
+/// How long the threads of closed connections get to finish.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn thread_count() -> usize {
+    fs::read_dir("/proc/self/task")
+        .expect("lists the process' threads")
+        .count()
+}
+
+fn free_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Waits for the thread count to drop to `count`, returning the last one
+/// seen.
+async fn settle(count: usize) -> usize {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    loop {
+        let threads = thread_count();
+        if threads <= count || Instant::now() > deadline {
+            return threads;
+        }
+        task::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+async fn connect(addr: &str) -> TcpStream {
+    let deadline = Instant::now() + SETTLE_TIMEOUT;
+    loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return stream,
+            Err(e) if Instant::now() > deadline => panic!("server never came up: {}", e),
+            Err(_) => task::sleep(Duration::from_millis(50)).await,
+        }
+    }
+}
+
+/// Starts a session, which starts an xi-core, then closes the connection.
+async fn connect_and_close(addr: &str, endpoint: &Url) {
+    let stream = connect(addr).await;
+    let (mut ws, _) = async_tungstenite::client_async(endpoint.clone(), stream)
+        .await
+        .expect("websocket handshake");
+    let started = r#"{"method":"client_started","params":{}}"#;
+    ws.send(Message::Text(started.into())).await.unwrap();
+    let connected = ws.next().await.expect("an answer").unwrap();
+    assert!(connected.to_text().unwrap().contains("connected"));
+
+    ws.send(Message::Close(None)).await.unwrap();
+    // read until the server's close comes back
+    while let Some(Ok(_)) = ws.next().await {}
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn closed_connections_leave_no_threads() {
+    let dir = TempDir::new().unwrap();
+    let config = Config {
+        addr: format!("127.0.0.1:{}", free_port()),
+        config_dir: Some(dir.path().to_path_buf()),
+        files: Vec::new(),
+        workspace: dir.path().to_path_buf(),
+        static_dir: dir.path().to_path_buf(),
+        shared: false,
+    };
+    let addr = config.addr.clone();
+    let endpoint = Url::parse(&config.endpoint()).unwrap();
+    task::spawn(start_websocket_server(config));
+
+    task::block_on(async {
+        // the first connection starts the runtime's own threads, which stay
+        connect_and_close(&addr, &endpoint).await;
+        task::sleep(Duration::from_millis(500)).await;
+        let baseline = thread_count();
+
+        for _ in 0..CYCLES {
+            connect_and_close(&addr, &endpoint).await;
+        }
+        let threads = settle(baseline).await;
+        assert!(
+            threads <= baseline,
+            "{} threads after {} connections, {} before",
+            threads,
+            CYCLES,
+            baseline
+        );
+    });
+}